@@ -1,8 +1,181 @@
-#[derive(Clone, Copy)]
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::filter::RowFilter;
+use crate::hyperloglog::{MAX_PRECISION, MIN_PRECISION};
+use crate::mapping::ColumnMapping;
+use crate::normalize::{canonical_normalizers, normalizer_names, StringNormalizer};
+use crate::row_hasher::{Blake3, Murmur3, RowHasher};
+use crate::selection::ColumnSelection;
+
+/// Order in which a row's columns are combined before hashing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnOrder {
+    /// Columns are taken in the order they appear in the Schema
+    Positional,
+    /// Columns are sorted by name and each value is keyed by its column name,
+    /// so reordering the columns of a dataset does not change its hash
+    ByName,
+}
+
+/// How the hashes of individual rows are combined into one dataset hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MultisetHash {
+    /// Wrapping sum of the 128-bit row hashes
+    Sum,
+    /// Additionally maintain a 2048-byte LtHash state, which cannot be forged
+    /// by crafting rows that cancel out
+    LtHash,
+}
+
+/// Size of the dataset hash, built from independent 128-bit row hash lanes.
+/// The first 128 bits of a wider hash always equal the 128-bit hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Width {
+    W128,
+    W256,
+    W512,
+}
+
+impl Width {
+    /// Number of 128-bit lanes
+    pub fn lanes(&self) -> usize {
+        match self {
+            Width::W128 => 1,
+            Width::W256 => 2,
+            Width::W512 => 4,
+        }
+    }
+}
+
+/// Normalization settings for a single column, overriding the config-wide values
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColumnOptions {
+    pub digits: Option<usize>,
+    pub characters: Option<usize>,
+    pub string_normalizers: Option<Vec<StringNormalizer>>,
+}
+
+impl ColumnOptions {
+    /// Settings that differ from the config-wide values, as `(setting, value)` pairs
+    fn overrides(&self) -> Vec<(&'static str, String)> {
+        let mut overrides = Vec::new();
+        if let Some(digits) = self.digits {
+            overrides.push(("digits", digits.to_string()));
+        }
+        if let Some(characters) = self.characters {
+            overrides.push(("characters", characters.to_string()));
+        }
+        if let Some(normalizers) = &self.string_normalizers {
+            overrides.push((
+                "string_normalizers",
+                normalizer_names(&canonical_normalizers(normalizers)),
+            ));
+        }
+        overrides
+    }
+}
+
+/// Version of the algorithm that serializes each row before it is hashed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    /// The original encoding, with each value terminated by `\n\0`. Values
+    /// containing the terminator can collide, and floats are not rounded
+    /// correctly. Kept to reproduce existing hashes.
+    V1,
+    /// Each value is type-tagged and length-prefixed, so different rows always
+    /// serialize differently
+    V2,
+}
+
+impl Version {
+    pub fn number(&self) -> u32 {
+        match self {
+            Version::V1 => 1,
+            Version::V2 => 2,
+        }
+    }
+}
+
+/// Settings that cannot be combined into a config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// A key was given for a row hash function that cannot be keyed
+    KeyNotSupported(&'static str),
+    /// Numbers cannot be rounded to 0 significant digits
+    ZeroDigits,
+    /// Hashing at other digits needs version 2, which rounds floats correctly
+    RoundingNeedsV2,
+    /// The HyperLogLog precision is outside the supported range
+    InvalidPrecision(u8),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::KeyNotSupported(hasher) => write!(
+                f,
+                "{} does not support a key, use siphash or blake3",
+                hasher
+            ),
+            ConfigError::ZeroDigits => write!(f, "digits must be at least 1"),
+            ConfigError::InvalidPrecision(precision) => write!(
+                f,
+                "HyperLogLog precision {} is not between {} and {}",
+                precision, MIN_PRECISION, MAX_PRECISION
+            ),
+            ConfigError::RoundingNeedsV2 => write!(
+                f,
+                "version 1 does not round floats correctly, use version 2 to hash at other digits"
+            ),
+        }
+    }
+}
+
+/// Check numbers can be hashed at `digits` significant digits besides the
+/// configured ones
+fn check_rounding(digits: usize, version: Version) -> Result<(), ConfigError> {
+    if digits == 0 {
+        return Err(ConfigError::ZeroDigits);
+    }
+    if version == Version::V1 {
+        return Err(ConfigError::RoundingNeedsV2);
+    }
+    Ok(())
+}
+
+impl Error for ConfigError {}
+
+#[derive(Clone)]
 pub struct IdHashConfigBuilder {
     digits: Option<u32>,
     characters: Option<usize>,
     truncation: Option<usize>,
+    schema: Option<bool>,
+    column_order: Option<ColumnOrder>,
+    column_fingerprints: Option<bool>,
+    min_max: Option<bool>,
+    hasher: Option<Arc<dyn RowHasher>>,
+    key: Option<[u8; 32]>,
+    multiset: Option<MultisetHash>,
+    width: Option<Width>,
+    unf6: Option<bool>,
+    order_sensitive: Option<bool>,
+    selection: Option<ColumnSelection>,
+    mapping: Option<ColumnMapping>,
+    filter: Option<RowFilter>,
+    numeric_invariant: Option<bool>,
+    null_tokens: Option<Vec<String>>,
+    string_normalizers: Option<Vec<StringNormalizer>>,
+    version: Option<Version>,
+    column_options: BTreeMap<String, ColumnOptions>,
+    resolutions: Option<Vec<usize>>,
+    sketch: Option<usize>,
+    min_hash: Option<usize>,
+    hyperloglog: Option<u8>,
 }
 
 impl Default for IdHashConfigBuilder {
@@ -17,6 +190,28 @@ impl IdHashConfigBuilder {
             digits: None,
             characters: None,
             truncation: None,
+            schema: None,
+            column_order: None,
+            column_fingerprints: None,
+            min_max: None,
+            hasher: None,
+            key: None,
+            multiset: None,
+            width: None,
+            unf6: None,
+            order_sensitive: None,
+            selection: None,
+            mapping: None,
+            filter: None,
+            numeric_invariant: None,
+            null_tokens: None,
+            string_normalizers: None,
+            version: None,
+            column_options: BTreeMap::new(),
+            resolutions: None,
+            sketch: None,
+            min_hash: None,
+            hyperloglog: None,
         }
     }
 
@@ -35,30 +230,390 @@ impl IdHashConfigBuilder {
         self
     }
 
-    pub fn build(&self) -> IdHashConfig {
-        IdHashConfig {
+    /// Normalize every numeric type to the UNF version 6 numeric representation,
+    /// so e.g. `1` read as an integer and `1.0` read as a float hash equally
+    pub fn numeric_invariant(&mut self, x: bool) -> &mut IdHashConfigBuilder {
+        self.numeric_invariant = Some(x);
+        self
+    }
+
+    /// Values treated as null, such as `NA` or the empty string.
+    /// `NaN` also makes floating point NaN values null.
+    pub fn null_tokens(&mut self, x: Vec<String>) -> &mut IdHashConfigBuilder {
+        self.null_tokens = Some(x);
+        self
+    }
+
+    /// Normalize text values before hashing, e.g. to ignore trailing spaces
+    pub fn string_normalizers(&mut self, x: Vec<StringNormalizer>) -> &mut IdHashConfigBuilder {
+        self.string_normalizers = Some(x);
+        self
+    }
+
+    /// Row encoding, `Version::V2` by default. `Version::V1` reproduces hashes
+    /// produced before V2 was introduced.
+    pub fn version(&mut self, x: Version) -> &mut IdHashConfigBuilder {
+        self.version = Some(x);
+        self
+    }
+
+    /// Override the normalization settings of the column hashed under `name`
+    pub fn column_options(&mut self, name: &str, x: ColumnOptions) -> &mut IdHashConfigBuilder {
+        self.column_options.insert(String::from(name), x);
+        self
+    }
+
+    /// Also compute the data and column hashes with numbers rounded to each of
+    /// these numbers of significant digits, in the same pass. Needs version 2.
+    pub fn resolutions(&mut self, x: Vec<usize>) -> &mut IdHashConfigBuilder {
+        self.resolutions = Some(x);
+        self
+    }
+
+    /// Also build an IBLT sketch of the row hashes with this many cells, from
+    /// which the rows differing from another dataset can be recovered
+    pub fn sketch(&mut self, cells: usize) -> &mut IdHashConfigBuilder {
+        self.sketch = Some(cells);
+        self
+    }
+
+    /// Also keep a bottom-k MinHash sketch of the `k` smallest row hashes, to
+    /// estimate how similar two datasets are
+    pub fn min_hash(&mut self, k: usize) -> &mut IdHashConfigBuilder {
+        self.min_hash = Some(k);
+        self
+    }
+
+    /// Also estimate the number of distinct rows with a HyperLogLog sketch of
+    /// `2^precision` registers, 14 giving an error of about 0.8%. The precision
+    /// must be between 4 and 18.
+    pub fn hyperloglog(&mut self, precision: u8) -> &mut IdHashConfigBuilder {
+        self.hyperloglog = Some(precision);
+        self
+    }
+
+    /// Include the field names, types and nullability in the fingerprint
+    pub fn schema(&mut self, x: bool) -> &mut IdHashConfigBuilder {
+        self.schema = Some(x);
+        self
+    }
+
+    pub fn column_order(&mut self, x: ColumnOrder) -> &mut IdHashConfigBuilder {
+        self.column_order = Some(x);
+        self
+    }
+
+    /// Ignore both row order and column order: columns are matched by name,
+    /// and every column is fingerprinted so differences can still be located.
+    /// `false` leaves the column order and column fingerprint settings as they are.
+    pub fn fully_invariant(&mut self, x: bool) -> &mut IdHashConfigBuilder {
+        if x {
+            self.column_order = Some(ColumnOrder::ByName);
+            self.column_fingerprints = Some(true);
+        }
+        self
+    }
+
+    /// Also produce an order-invariant hash for every column
+    pub fn column_fingerprints(&mut self, x: bool) -> &mut IdHashConfigBuilder {
+        self.column_fingerprints = Some(x);
+        self
+    }
+
+    /// Record the minimum and maximum of every column in the summary
+    pub fn min_max(&mut self, x: bool) -> &mut IdHashConfigBuilder {
+        self.min_max = Some(x);
+        self
+    }
+
+    /// Hash function applied to each row, MurmurHash3 by default
+    pub fn hasher(&mut self, x: Arc<dyn RowHasher>) -> &mut IdHashConfigBuilder {
+        self.hasher = Some(x);
+        self
+    }
+
+    /// Secret key for the row hash, so that a published hash cannot be used
+    /// to test whether a given row is in the dataset.
+    /// Uses keyed BLAKE3 unless another keyed hasher is selected.
+    pub fn key(&mut self, x: [u8; 32]) -> &mut IdHashConfigBuilder {
+        self.key = Some(x);
+        self
+    }
+
+    pub fn multiset(&mut self, x: MultisetHash) -> &mut IdHashConfigBuilder {
+        self.multiset = Some(x);
+        self
+    }
+
+    pub fn width(&mut self, x: Width) -> &mut IdHashConfigBuilder {
+        self.width = Some(x);
+        self
+    }
+
+    /// Also compute genuine UNF version 6 values for every column and the dataset,
+    /// using `digits`, `characters` and `truncation`. Requires batches in order.
+    pub fn unf6(&mut self, x: bool) -> &mut IdHashConfigBuilder {
+        self.unf6 = Some(x);
+        self
+    }
+
+    /// Also produce a hash that depends on row order, to detect re-sorted data.
+    /// Requires batches in order.
+    pub fn order_sensitive(&mut self, x: bool) -> &mut IdHashConfigBuilder {
+        self.order_sensitive = Some(x);
+        self
+    }
+
+    /// Columns to hash, every column by default
+    pub fn selection(&mut self, x: ColumnSelection) -> &mut IdHashConfigBuilder {
+        self.selection = Some(x);
+        self
+    }
+
+    /// Rename and reorder columns before hashing, to line up with another dataset
+    pub fn mapping(&mut self, x: ColumnMapping) -> &mut IdHashConfigBuilder {
+        self.mapping = Some(x);
+        self
+    }
+
+    /// Only hash the rows matching the filter, every row by default
+    pub fn filter(&mut self, x: RowFilter) -> &mut IdHashConfigBuilder {
+        self.filter = Some(x);
+        self
+    }
+
+    /// # Errors
+    /// If the settings cannot be combined, such as a key with a hasher that
+    /// cannot be keyed, or numbers are rounded to 0 digits
+    pub fn build(&self) -> Result<IdHashConfig, ConfigError> {
+        let version = self.version.unwrap_or(Version::V2);
+        let mut column_digits = self.column_options.values().map(|options| options.digits);
+        if self.digits == Some(0) || column_digits.any(|digits| digits == Some(0)) {
+            return Err(ConfigError::ZeroDigits);
+        }
+        let mut resolutions = self.resolutions.clone().unwrap_or_default();
+        resolutions.sort_unstable();
+        resolutions.dedup();
+        for &digits in &resolutions {
+            check_rounding(digits, version)?;
+        }
+        if let Some(precision) = self.hyperloglog {
+            if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+                return Err(ConfigError::InvalidPrecision(precision));
+            }
+        }
+        Ok(IdHashConfig {
             digits: if let Some(digits) = self.digits {
                 digits as usize
             } else {
                 7
             },
-            truncation: if let Some(truncation) = self.truncation {
-                truncation
-            } else {
-                128
-            },
-            characters: if let Some(characters) = self.characters {
-                characters
-            } else {
-                128
+            truncation: self.truncation.unwrap_or(128),
+            characters: self.characters.unwrap_or(128),
+            schema: self.schema.unwrap_or(false),
+            column_order: self.column_order.unwrap_or(ColumnOrder::Positional),
+            column_fingerprints: self.column_fingerprints.unwrap_or(false),
+            min_max: self.min_max.unwrap_or(false),
+            hasher: match (&self.hasher, &self.key) {
+                (Some(hasher), Some(key)) => hasher
+                    .with_key(key)
+                    .ok_or(ConfigError::KeyNotSupported(hasher.name()))?,
+                (Some(hasher), None) => hasher.clone(),
+                (None, Some(key)) => Arc::new(Blake3::keyed(*key)),
+                (None, None) => Arc::new(Murmur3),
             },
-        }
+            multiset: self.multiset.unwrap_or(MultisetHash::Sum),
+            width: self.width.unwrap_or(Width::W128),
+            unf6: self.unf6.unwrap_or(false),
+            order_sensitive: self.order_sensitive.unwrap_or(false),
+            selection: self.selection.clone().unwrap_or_default(),
+            mapping: self.mapping.clone().unwrap_or_default(),
+            filter: self.filter.clone().unwrap_or_default(),
+            numeric_invariant: self.numeric_invariant.unwrap_or(false),
+            null_tokens: self.null_tokens.clone().unwrap_or_default(),
+            string_normalizers: canonical_normalizers(
+                self.string_normalizers.as_deref().unwrap_or_default(),
+            ),
+            version,
+            column_options: self.column_options.clone(),
+            resolutions,
+            sketch: self.sketch,
+            min_hash: self.min_hash,
+            hyperloglog: self.hyperloglog,
+        })
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct IdHashConfig {
     pub digits: usize,
     pub truncation: usize,
     pub characters: usize,
+    pub schema: bool,
+    pub column_order: ColumnOrder,
+    pub column_fingerprints: bool,
+    pub min_max: bool,
+    pub hasher: Arc<dyn RowHasher>,
+    pub multiset: MultisetHash,
+    pub width: Width,
+    pub unf6: bool,
+    pub order_sensitive: bool,
+    pub selection: ColumnSelection,
+    pub mapping: ColumnMapping,
+    pub filter: RowFilter,
+    pub numeric_invariant: bool,
+    pub null_tokens: Vec<String>,
+    /// In the order they are applied
+    pub string_normalizers: Vec<StringNormalizer>,
+    pub version: Version,
+    /// Per-column overrides, keyed by the name the column is hashed under
+    pub column_options: BTreeMap<String, ColumnOptions>,
+    /// Extra `digits` levels to hash at, in ascending order
+    pub resolutions: Vec<usize>,
+    /// Cells of the IBLT sketch of the row hashes, if requested
+    pub sketch: Option<usize>,
+    /// Size `k` of the MinHash sketch of the row hashes, if requested
+    pub min_hash: Option<usize>,
+    /// Precision of the HyperLogLog sketch of the row hashes, if requested
+    pub hyperloglog: Option<u8>,
+}
+
+impl IdHashConfig {
+    /// The same settings with every number rounded to `digits` significant
+    /// digits, overriding any per-column digits
+    ///
+    /// # Errors
+    /// If `digits` is 0, or the config uses version 1
+    pub fn with_digits(&self, digits: usize) -> Result<IdHashConfig, ConfigError> {
+        check_rounding(digits, self.version)?;
+        let mut config = self.clone();
+        config.digits = digits;
+        config.resolutions = vec![];
+        config.sketch = None;
+        config.min_hash = None;
+        config.hyperloglog = None;
+        for options in config.column_options.values_mut() {
+            options.digits = None;
+        }
+        Ok(config)
+    }
+
+    /// Significant digits to round the numbers of a column to
+    pub fn digits_for(&self, column: &str) -> usize {
+        self.column_options
+            .get(column)
+            .and_then(|options| options.digits)
+            .unwrap_or(self.digits)
+    }
+
+    /// Normalizers applied to the text values of a column, in the order they are applied
+    pub fn string_normalizers_for(&self, column: &str) -> Vec<StringNormalizer> {
+        match self
+            .column_options
+            .get(column)
+            .and_then(|options| options.string_normalizers.as_ref())
+        {
+            Some(normalizers) => canonical_normalizers(normalizers),
+            None => self.string_normalizers.clone(),
+        }
+    }
+
+    /// Characters to truncate the values of a column to
+    pub fn characters_for(&self, column: &str) -> usize {
+        self.column_options
+            .get(column)
+            .and_then(|options| options.characters)
+            .unwrap_or(self.characters)
+    }
+
+    /// Settings recorded in the Fingerprint, which must agree for two
+    /// Fingerprints to be comparable. Every setting that changes the bytes a
+    /// row is hashed from is included when it differs from its default.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        let mut metadata = BTreeMap::new();
+        metadata.insert(String::from("version"), self.version.number().to_string());
+        metadata.insert(String::from("hasher"), String::from(self.hasher.name()));
+        if let Some(key_id) = self.hasher.key_id() {
+            metadata.insert(String::from("key_id"), key_id);
+        }
+        if self.multiset == MultisetHash::LtHash {
+            metadata.insert(String::from("multiset"), String::from("lthash16"));
+        }
+        if self.digits != 7 {
+            metadata.insert(String::from("digits"), self.digits.to_string());
+        }
+        if self.characters != 128 {
+            metadata.insert(String::from("characters"), self.characters.to_string());
+        }
+        if self.column_order == ColumnOrder::ByName {
+            metadata.insert(String::from("column_order"), String::from("by_name"));
+        }
+        for (setting, selectors) in [
+            ("include", &self.selection.include),
+            ("exclude", &self.selection.exclude),
+        ] {
+            if !selectors.is_empty() {
+                let selectors: Vec<String> = selectors.iter().map(ToString::to_string).collect();
+                metadata.insert(String::from(setting), format!("{:?}", selectors));
+            }
+        }
+        if !self.mapping.is_identity() {
+            metadata.insert(
+                String::from("mapping"),
+                format!("{:?}", self.mapping.renames),
+            );
+            if self.mapping.reorder {
+                metadata.insert(String::from("reorder"), String::from("true"));
+            }
+        }
+        if !self.filter.is_all() {
+            let predicates: Vec<String> = self
+                .filter
+                .predicates
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            metadata.insert(String::from("filter"), predicates.join(" && "));
+        }
+        if self.width != Width::W128 {
+            metadata.insert(
+                String::from("width"),
+                (self.width.lanes() * 128).to_string(),
+            );
+        }
+        if self.numeric_invariant {
+            metadata.insert(String::from("numeric"), String::from("unf6"));
+        }
+        if !self.null_tokens.is_empty() {
+            metadata.insert(
+                String::from("null_tokens"),
+                format!("{:?}", self.null_tokens),
+            );
+        }
+        if !self.string_normalizers.is_empty() {
+            metadata.insert(
+                String::from("string_normalizers"),
+                normalizer_names(&self.string_normalizers),
+            );
+        }
+        for (column, options) in &self.column_options {
+            for (setting, value) in options.overrides() {
+                metadata.insert(format!("column.{}.{}", column, setting), value);
+            }
+        }
+        metadata
+    }
+
+    /// Short hash of the metadata, stored in sketches so that sketches written
+    /// with different settings are not compared
+    pub fn settings_id(&self) -> u64 {
+        let mut hasher = blake3::Hasher::new();
+        for (setting, value) in self.metadata() {
+            hasher.update(&(setting.len() as u64).to_le_bytes());
+            hasher.update(setting.as_bytes());
+            hasher.update(&(value.len() as u64).to_le_bytes());
+            hasher.update(value.as_bytes());
+        }
+        u64::from_le_bytes(hasher.finalize().as_bytes()[..8].try_into().unwrap())
+    }
 }
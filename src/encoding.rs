@@ -0,0 +1,170 @@
+use arrow::{
+    array::{
+        Array, Int128Array, Int16Array, Int32Array, Int64Array, Int8Array, UInt16Array,
+        UInt32Array, UInt64Array, UInt8Array, Utf8Array,
+    },
+    datatypes::{DataType, Field},
+    types::Offset,
+};
+
+use crate::{
+    config::IdHashConfig, normalize::normalize_string, schema::is_numeric, unf6::column_to_unf6,
+    unf_vector::UNFVector,
+};
+
+/// Tag of a missing value, which is followed by no length or content
+const NULL_TAG: u8 = 0;
+
+/// Tag identifying the kind of value, so values of different types never
+/// encode the same way. `None` for types that cannot be hashed, e.g. lists.
+fn value_tag(data_type: &DataType, config: &IdHashConfig) -> Option<u8> {
+    let tag = match data_type {
+        // arrow2 has no array of half-precision floats to read the values from
+        DataType::Float16 => return None,
+        data_type if config.numeric_invariant && is_numeric(data_type) => b'n',
+        DataType::Boolean => b'b',
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => b'i',
+        DataType::Float32 | DataType::Float64 => b'f',
+        DataType::Decimal(_, _) => b'd',
+        DataType::Utf8 | DataType::LargeUtf8 => b's',
+        DataType::Date32 | DataType::Date64 => b'D',
+        DataType::Timestamp(_, _) => b'T',
+        _ => return None,
+    };
+    Some(tag)
+}
+
+/// Whether values of a type can be encoded in version 2
+pub(crate) fn is_encodable(data_type: &DataType, config: &IdHashConfig) -> bool {
+    value_tag(data_type, config).is_some()
+}
+
+/// Append a value as its tag, its length in bytes as a little-endian u64, then its
+/// UTF-8 bytes. A missing value is the null tag alone.
+pub fn encode_value(tag: u8, value: Option<&str>, out: &mut Vec<u8>) {
+    match value {
+        Some(value) => {
+            out.push(tag);
+            out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            out.extend_from_slice(value.as_bytes());
+        }
+        None => out.push(NULL_TAG),
+    }
+}
+
+/// Append a column name, length-prefixed so it cannot run into the value after it
+pub fn encode_key(name: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(name.len() as u64).to_le_bytes());
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn truncate(value: &str, characters: usize) -> &str {
+    let end = value
+        .char_indices()
+        .nth(characters)
+        .map_or(value.len(), |(index, _)| index);
+    &value[..end]
+}
+
+fn text_values<'a, O: Offset>(
+    col: &'a Utf8Array<O>,
+    field: &Field,
+    config: &IdHashConfig,
+) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+    let normalizers = config.string_normalizers_for(&field.name);
+    let characters = config.characters_for(&field.name);
+    Box::new(col.iter().map(move |x| {
+        x.map(|x| String::from(truncate(&normalize_string(x, &normalizers), characters)))
+    }))
+}
+
+/// Normalized text of each value of a column, `None` for missing values.
+///
+/// Integers and decimals are exact, unless numbers are type-invariant.
+/// Floats, booleans, dates and timestamps take their UNF version 6 form.
+fn column_values<'a>(
+    col: &'a dyn Array,
+    field: &Field,
+    config: &IdHashConfig,
+) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+    let digits = config.digits_for(&field.name);
+    let data_type = field.data_type();
+    let any = col.as_any();
+    match data_type {
+        data_type if config.numeric_invariant && is_numeric(data_type) => {
+            column_to_unf6(col, data_type, digits)
+        }
+        DataType::Int8 => any
+            .downcast_ref::<Int8Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::Int16 => any
+            .downcast_ref::<Int16Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::Int32 => any
+            .downcast_ref::<Int32Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::Int64 => any
+            .downcast_ref::<Int64Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::UInt8 => any
+            .downcast_ref::<UInt8Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::UInt16 => any
+            .downcast_ref::<UInt16Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::UInt32 => any
+            .downcast_ref::<UInt32Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::UInt64 => any
+            .downcast_ref::<UInt64Array>()
+            .expect("Failed to Downcast")
+            .to_unf(digits, true),
+        DataType::Decimal(_, _) => any
+            .downcast_ref::<Int128Array>()
+            .expect("Failed to downcast Decimal to Int128")
+            .to_unf(digits, true),
+        DataType::Utf8 => text_values(
+            any.downcast_ref::<Utf8Array<i32>>()
+                .expect("Failed to downcast to Utf-8"),
+            field,
+            config,
+        ),
+        DataType::LargeUtf8 => text_values(
+            any.downcast_ref::<Utf8Array<i64>>()
+                .expect("Failed to downcast to Utf-8"),
+            field,
+            config,
+        ),
+        data_type => column_to_unf6(col, data_type, digits),
+    }
+}
+
+/// Bytes of each value of a column in the version 2 encoding, which is
+/// length-prefixed and type-tagged so rows serialize injectively
+pub(crate) fn encode_column<'a>(
+    col: &'a dyn Array,
+    field: &Field,
+    config: &IdHashConfig,
+) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+    let tag = value_tag(field.data_type(), config)
+        .expect("column types are checked before any batch is hashed");
+    Box::new(column_values(col, field, config).map(move |value| {
+        let mut encoded = Vec::with_capacity(value.as_ref().map_or(1, |x| x.len() + 9));
+        encode_value(tag, value.as_deref(), &mut encoded);
+        encoded
+    }))
+}
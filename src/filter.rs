@@ -0,0 +1,308 @@
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::{
+    array::BooleanArray,
+    chunk::Chunk,
+    compute::filter::filter_chunk,
+    datatypes::{DataType, Schema, TimeUnit},
+};
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::summary::{column_values, Value};
+use crate::utils::ThreadArrayChunk;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl CompareOp {
+    const SYMBOLS: [(&'static str, CompareOp); 7] = [
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::NotEq),
+        ("<=", CompareOp::LtEq),
+        (">=", CompareOp::GtEq),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+        ("=", CompareOp::Eq),
+    ];
+
+    fn symbol(&self) -> &'static str {
+        CompareOp::SYMBOLS
+            .iter()
+            .find(|(_, op)| op == self)
+            .map(|(symbol, _)| *symbol)
+            .expect("Every operator has a symbol")
+    }
+
+    fn matches(&self, left: &Value, right: &Value) -> bool {
+        match self {
+            CompareOp::Eq => left == right,
+            CompareOp::NotEq => left != right,
+            CompareOp::Lt => left < right,
+            CompareOp::LtEq => left <= right,
+            CompareOp::Gt => left > right,
+            CompareOp::GtEq => left >= right,
+        }
+    }
+}
+
+/// A comparison between a column and a literal, e.g. `region == 'EU'`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Predicate {
+    pub column: String,
+    pub op: CompareOp,
+    /// Parsed according to the column's type when the filter is applied
+    pub literal: String,
+}
+
+/// Rows to hash, by default every row. A row is hashed only if it matches every
+/// predicate; null values never match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RowFilter {
+    pub predicates: Vec<Predicate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    Syntax(String),
+    UnknownColumn(String),
+    InvalidLiteral { column: String, literal: String },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Syntax(term) => write!(f, "expected `column op value`, found `{}`", term),
+            FilterError::UnknownColumn(column) => write!(f, "no column named `{}`", column),
+            FilterError::InvalidLiteral { column, literal } => {
+                write!(
+                    f,
+                    "`{}` cannot be compared with column `{}`",
+                    literal, column
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+fn unquote(x: &str) -> &str {
+    let x = x.trim();
+    for quote in QUOTES {
+        if x.len() >= 2 && x.starts_with(quote) && x.ends_with(quote) {
+            return &x[1..x.len() - 1];
+        }
+    }
+    x
+}
+
+const QUOTES: [char; 3] = ['\'', '"', '`'];
+
+/// Byte offsets of the characters of `x` outside quoted literals, with the rest of
+/// the string from each of them
+fn unquoted(x: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut quote = None;
+    x.char_indices().filter_map(move |(index, c)| match quote {
+        Some(open) => {
+            if c == open {
+                quote = None;
+            }
+            None
+        }
+        None if QUOTES.contains(&c) => {
+            quote = Some(c);
+            None
+        }
+        None => Some((index, &x[index..])),
+    })
+}
+
+/// Split an expression on `and` and `&&`, except inside quoted literals
+fn split_terms(expression: &str) -> Vec<&str> {
+    let mut terms = vec![];
+    let mut start = 0;
+    let mut skip_to = 0;
+    for (index, rest) in unquoted(expression) {
+        if index < skip_to {
+            continue;
+        }
+        let separator = if rest.starts_with("&&") {
+            2
+        } else if rest.starts_with("and")
+            && expression[..index].ends_with(char::is_whitespace)
+            && rest[3..].starts_with(char::is_whitespace)
+        {
+            3
+        } else {
+            continue;
+        };
+        terms.push(&expression[start..index]);
+        start = index + separator;
+        skip_to = start;
+    }
+    terms.push(&expression[start..]);
+    terms
+}
+
+/// Quotes the column and literal, so the text is the same however they were
+/// quoted when parsed
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} {:?}",
+            self.column,
+            self.op.symbol(),
+            self.literal
+        )
+    }
+}
+
+impl Predicate {
+    fn parse(term: &str) -> Result<Self, FilterError> {
+        unquoted(term)
+            // The leftmost operator outside quotes, preferring the longest symbol
+            .find_map(|(index, rest)| {
+                CompareOp::SYMBOLS
+                    .iter()
+                    .find(|(symbol, _)| rest.starts_with(symbol))
+                    .map(|(symbol, op)| (index, symbol, op))
+            })
+            .map(|(index, symbol, op)| Predicate {
+                column: unquote(&term[..index]).to_string(),
+                op: *op,
+                literal: unquote(&term[index + symbol.len()..]).to_string(),
+            })
+            .filter(|predicate| !predicate.column.is_empty())
+            .ok_or_else(|| FilterError::Syntax(term.trim().to_string()))
+    }
+}
+
+impl RowFilter {
+    /// Parse comparisons joined by `and` or `&&`, e.g. `region == 'EU' and date >= 2024-01-01`
+    pub fn parse(expression: &str) -> Result<Self, FilterError> {
+        let predicates = split_terms(expression)
+            .into_iter()
+            .map(Predicate::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(RowFilter { predicates })
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.predicates.is_empty()
+    }
+
+    /// Resolve the columns and literals of the filter against a Schema
+    pub fn bind(&self, schema: &Schema) -> Result<BoundFilter, FilterError> {
+        let predicates = self
+            .predicates
+            .iter()
+            .map(|predicate| {
+                let index = schema
+                    .fields
+                    .iter()
+                    .position(|field| field.name == predicate.column)
+                    .ok_or_else(|| FilterError::UnknownColumn(predicate.column.clone()))?;
+                let data_type = schema.fields[index].data_type().clone();
+                let literal = parse_literal(&predicate.literal, &data_type).ok_or_else(|| {
+                    FilterError::InvalidLiteral {
+                        column: predicate.column.clone(),
+                        literal: predicate.literal.clone(),
+                    }
+                })?;
+                Ok((index, data_type, predicate.op, literal))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(BoundFilter { predicates })
+    }
+}
+
+fn parse_date(x: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(x, "%Y-%m-%d").ok()
+}
+
+fn parse_timestamp(x: &str) -> Option<NaiveDateTime> {
+    let x = x.trim_end_matches('Z');
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(x, format).ok())
+        .or_else(|| parse_date(x).map(|date| date.and_hms(0, 0, 0)))
+}
+
+/// The literal as a Value comparable with the column's values
+fn parse_literal(literal: &str, data_type: &DataType) -> Option<Value> {
+    let epoch = NaiveDate::from_ymd(1970, 1, 1);
+    match data_type {
+        DataType::Boolean => literal.parse().ok().map(Value::Boolean),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => {
+            literal.parse().ok().map(Value::Integer)
+        }
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => {
+            literal.parse().ok().map(Value::UInteger)
+        }
+        DataType::Float32 | DataType::Float64 => literal.parse().ok().map(Value::Float),
+        DataType::Utf8 | DataType::LargeUtf8 => Some(Value::Utf8(literal.to_string())),
+        DataType::Date32 => {
+            parse_date(literal).map(|date| Value::Integer((date - epoch).num_days()))
+        }
+        DataType::Date64 => {
+            parse_date(literal).map(|date| Value::Integer((date - epoch).num_milliseconds()))
+        }
+        DataType::Timestamp(unit, _) => parse_timestamp(literal).and_then(|timestamp| {
+            let since_epoch = timestamp - epoch.and_hms(0, 0, 0);
+            match unit {
+                TimeUnit::Second => Some(since_epoch.num_seconds()),
+                TimeUnit::Millisecond => Some(since_epoch.num_milliseconds()),
+                TimeUnit::Microsecond => since_epoch.num_microseconds(),
+                TimeUnit::Nanosecond => since_epoch.num_nanoseconds(),
+            }
+            .map(Value::Integer)
+        }),
+        _ => None,
+    }
+}
+
+/// A RowFilter resolved against a Schema, ready to apply to batches
+#[derive(Clone, Debug)]
+pub struct BoundFilter {
+    predicates: Vec<(usize, DataType, CompareOp, Value)>,
+}
+
+impl BoundFilter {
+    fn mask(&self, input: &Chunk<ThreadArrayChunk>) -> Vec<bool> {
+        let mut mask = vec![true; input.len()];
+        for (index, data_type, op, literal) in &self.predicates {
+            let values = column_values(input.columns()[*index].as_ref(), data_type)
+                .expect("Filter columns are checked when binding");
+            for (keep, value) in mask.iter_mut().zip(values) {
+                *keep = *keep && value.is_some_and(|value| op.matches(&value, literal));
+            }
+        }
+        mask
+    }
+
+    /// Drop the rows of a batch that do not match, before they are hashed
+    pub fn apply(&self, input: Chunk<ThreadArrayChunk>) -> Chunk<ThreadArrayChunk> {
+        let mask = self.mask(&input);
+        if mask.iter().all(|keep| *keep) {
+            return input;
+        }
+        let filtered = filter_chunk(&input, &BooleanArray::from_slice(mask))
+            .expect("Mask has one value per row");
+        Chunk::new(
+            filtered
+                .into_arrays()
+                .into_iter()
+                .map(|array| ThreadArrayChunk::from(Arc::new(array)))
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use arrow::datatypes::{DataType, Schema};
+
+use crate::{
+    config::{ColumnOrder, IdHashConfig},
+    filter::FilterError,
+    hash_builder::HashState,
+    hyperloglog::HyperLogLog,
+    iblt::Iblt,
+    minhash::MinHash,
+    multiset::LtHash,
+    schema::schema_hash,
+    summary::{ColumnSummary, Summary},
+    unf6::Unf6,
+};
+
+/// Order-invariant hash of a single column
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnFingerprint {
+    pub name: String,
+    pub hash: u128,
+}
+
+/// Reasons a dataset cannot be hashed with a config, found before any batch is hashed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FingerprintError {
+    /// The row filter names a missing column, or a value of the wrong type
+    Filter(FilterError),
+    /// No column is left to hash, e.g. the column selection matches none
+    NoColumns,
+    /// A column has a type whose values cannot be hashed, e.g. a list
+    UnsupportedType { column: String, data_type: DataType },
+}
+
+impl fmt::Display for FingerprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FingerprintError::Filter(err) => write!(f, "invalid row filter: {}", err),
+            FingerprintError::NoColumns => {
+                write!(f, "no columns to hash, check the column selection")
+            }
+            FingerprintError::UnsupportedType { column, data_type } => {
+                write!(f, "cannot hash column `{}` of type {:?}", column, data_type)
+            }
+        }
+    }
+}
+
+impl Error for FingerprintError {}
+
+impl From<FilterError> for FingerprintError {
+    fn from(err: FilterError) -> Self {
+        FingerprintError::Filter(err)
+    }
+}
+
+/// Hashes of the data with numbers rounded to another number of significant digits
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub digits: usize,
+    pub data: u128,
+    /// Hash of every column at this resolution, if requested
+    pub columns: Vec<ColumnFingerprint>,
+}
+
+/// Result of hashing a dataset
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+    /// Row-invariant hash of the data itself
+    pub data: u128,
+    /// All lanes of a 256 or 512-bit data hash, starting with `data`
+    pub wide: Vec<u128>,
+    /// Hash of the field names, logical types and nullability, if requested
+    pub schema: Option<u128>,
+    /// Hash of the data that depends on row order, if requested
+    pub ordered: Option<u128>,
+    /// Secure multiset hash of the data, if requested
+    pub lthash: Option<LtHash>,
+    /// Hash of every column in Schema order, if requested
+    pub columns: Vec<ColumnFingerprint>,
+    /// Row count, null counts and optionally bounds of every column
+    pub summary: Summary,
+    /// Settings the Fingerprint was produced with, such as the row hash function
+    pub metadata: BTreeMap<String, String>,
+    /// UNF version 6 of every column and of the dataset, if requested
+    pub unf: Option<Unf6>,
+    /// Hashes at each extra `digits` level, in ascending order of digits
+    pub resolutions: Vec<Resolution>,
+    /// IBLT sketch of the row hashes, if requested
+    pub sketch: Option<Iblt>,
+    /// Bottom-k MinHash sketch of the row hashes, if requested
+    pub min_hash: Option<MinHash>,
+    /// HyperLogLog sketch of the row hashes, if requested
+    pub hyperloglog: Option<HyperLogLog>,
+}
+
+impl Fingerprint {
+    pub(crate) fn new(
+        state: HashState,
+        unf: Option<Unf6>,
+        schema: &Schema,
+        config: &IdHashConfig,
+    ) -> Self {
+        let mut bounds = state.bounds.into_iter();
+        let summary = Summary {
+            rows: state.rows,
+            columns: schema
+                .fields
+                .iter()
+                .zip(state.null_counts.into_iter().chain(std::iter::repeat(0)))
+                .map(|(field, null_count)| {
+                    let (min, max) = match bounds.next().flatten() {
+                        Some((min, max)) => (Some(min), Some(max)),
+                        None => (None, None),
+                    };
+                    ColumnSummary {
+                        name: field.name.clone(),
+                        null_count,
+                        min,
+                        max,
+                    }
+                })
+                .collect(),
+        };
+        let column_fingerprints = |hashes: Vec<u128>| -> Vec<ColumnFingerprint> {
+            hashes
+                .into_iter()
+                .zip(schema.fields.iter())
+                .map(|(hash, field)| ColumnFingerprint {
+                    name: field.name.clone(),
+                    hash,
+                })
+                .collect()
+        };
+        let mut fingerprint = Fingerprint {
+            data: state.data,
+            wide: state.lanes,
+            schema: config.schema.then(|| schema_hash(schema, config)),
+            lthash: state.lthash,
+            ordered: state.ordered.map(|ordered| ordered.hash),
+            columns: column_fingerprints(state.columns),
+            summary,
+            metadata: config.metadata(),
+            unf,
+            resolutions: config
+                .resolutions
+                .iter()
+                .zip(state.resolutions)
+                .map(|(&digits, resolution)| Resolution {
+                    digits,
+                    data: resolution.data,
+                    columns: column_fingerprints(resolution.columns),
+                })
+                .collect(),
+            // Without any batch, e.g. when every row is filtered out, the
+            // state has no sketches, though the empty ones are still valid
+            sketch: state
+                .sketch
+                .or_else(|| config.sketch.map(Iblt::new))
+                .map(|sketch| sketch.with_settings(config.settings_id())),
+            min_hash: state
+                .min_hash
+                .or_else(|| config.min_hash.map(MinHash::new))
+                .map(|min_hash| min_hash.with_settings(config.settings_id())),
+            hyperloglog: state
+                .hyperloglog
+                .or_else(|| config.hyperloglog.map(HyperLogLog::new))
+                .map(|hyperloglog| hyperloglog.with_settings(config.settings_id())),
+        };
+        if config.column_order == ColumnOrder::ByName {
+            // Report columns in the same canonical order they were hashed in,
+            // so that the whole Fingerprint is independent of column order
+            fingerprint.columns.sort_by(|a, b| a.name.cmp(&b.name));
+            fingerprint
+                .summary
+                .columns
+                .sort_by(|a, b| a.name.cmp(&b.name));
+            if let Some(unf) = fingerprint.unf.as_mut() {
+                unf.columns.sort();
+            }
+            for resolution in fingerprint.resolutions.iter_mut() {
+                resolution.columns.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+        }
+        fingerprint
+    }
+
+    /// Single value covering both the data and, if present, the schema
+    pub fn combined(&self) -> u128 {
+        match self.schema {
+            Some(schema) => self.data.wrapping_add(schema),
+            None => self.data,
+        }
+    }
+
+    /// Estimated number of distinct rows, if a HyperLogLog sketch was requested
+    pub fn distinct_rows(&self) -> Option<f64> {
+        self.hyperloglog.as_ref().map(HyperLogLog::estimate)
+    }
+
+    /// Hexadecimal form of the data hash, covering every lane of a wide hash
+    pub fn to_hex(&self) -> String {
+        if self.wide.is_empty() {
+            format!("{:032x}", self.data)
+        } else {
+            self.wide
+                .iter()
+                .map(|lane| format!("{:032x}", lane))
+                .collect()
+        }
+    }
+
+    /// Compare against another Fingerprint, component by component
+    pub fn compare(&self, other: &Fingerprint) -> Comparison {
+        Comparison {
+            schema_matches: match (self.schema, other.schema) {
+                (Some(left), Some(right)) => Some(left == right),
+                _ => None,
+            },
+            data_matches: self.data == other.data
+                && self
+                    .wide
+                    .iter()
+                    .zip(other.wide.iter())
+                    .all(|(left, right)| left == right)
+                && match (&self.lthash, &other.lthash) {
+                    (Some(left), Some(right)) => left == right,
+                    _ => true,
+                },
+            order_matches: match (self.ordered, other.ordered) {
+                (Some(left), Some(right)) => Some(left == right),
+                _ => None,
+            },
+            differing_columns: differing_columns(&self.columns, &other.columns),
+            matching_resolutions: self
+                .resolutions
+                .iter()
+                .filter(|left| {
+                    other
+                        .resolutions
+                        .iter()
+                        .any(|right| right.digits == left.digits && right.data == left.data)
+                })
+                .map(|resolution| resolution.digits)
+                .collect(),
+            differing_resolutions: self
+                .resolutions
+                .iter()
+                .filter(|left| {
+                    other
+                        .resolutions
+                        .iter()
+                        .any(|right| right.digits == left.digits && right.data != left.data)
+                })
+                .map(|resolution| resolution.digits)
+                .collect(),
+            row_counts: (self.summary.rows, other.summary.rows),
+            differing_settings: self
+                .metadata
+                .iter()
+                .filter(|(key, value)| other.metadata.get(*key) != Some(value))
+                .map(|(key, _)| key)
+                .chain(
+                    other
+                        .metadata
+                        .keys()
+                        .filter(|key| !self.metadata.contains_key(*key)),
+                )
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Names of the columns whose hashes differ, or which only exist on one side.
+/// Columns are matched by name.
+fn differing_columns(left: &[ColumnFingerprint], right: &[ColumnFingerprint]) -> Vec<String> {
+    if left.is_empty() || right.is_empty() {
+        return vec![];
+    }
+    let find = |columns: &[ColumnFingerprint], name: &str| {
+        columns
+            .iter()
+            .find(|column| column.name == name)
+            .map(|column| column.hash)
+    };
+    left.iter()
+        .filter(|column| find(right, &column.name) != Some(column.hash))
+        .chain(
+            right
+                .iter()
+                .filter(|column| find(left, &column.name).is_none()),
+        )
+        .map(|column| column.name.clone())
+        .collect()
+}
+
+/// Component-wise outcome of comparing two Fingerprints
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comparison {
+    /// `None` when either side was hashed without its schema
+    pub schema_matches: Option<bool>,
+    pub data_matches: bool,
+    /// `None` when either side was hashed without its row order
+    pub order_matches: Option<bool>,
+    /// Empty when either side was hashed without column fingerprints
+    pub differing_columns: Vec<String>,
+    /// `digits` levels hashed on both sides whose data hashes are equal, in ascending order
+    pub matching_resolutions: Vec<usize>,
+    /// `digits` levels hashed on both sides whose data hashes differ, in ascending order
+    pub differing_resolutions: Vec<usize>,
+    pub row_counts: (u64, u64),
+    /// Metadata keys whose values differ, in which case the hashes are not comparable
+    pub differing_settings: Vec<String>,
+}
+
+impl Comparison {
+    pub fn is_match(&self) -> bool {
+        self.data_matches
+            && self.schema_matches.unwrap_or(true)
+            && self.order_matches.unwrap_or(true)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_match() {
+            return write!(f, "identical");
+        }
+        let mut differences = Vec::new();
+        if !self.differing_settings.is_empty() {
+            differences.push(format!(
+                "hashed with different settings ({})",
+                self.differing_settings.join(", ")
+            ));
+        }
+        if self.schema_matches == Some(false) {
+            differences.push(String::from("schema differs"));
+        }
+        if !self.data_matches {
+            differences.push(String::from("data differs"));
+            match (
+                self.matching_resolutions.last(),
+                self.differing_resolutions.first(),
+            ) {
+                (Some(matching), Some(differing)) => differences.push(format!(
+                    "equal to {} significant digits, not {}",
+                    matching, differing
+                )),
+                (Some(matching), None) => {
+                    differences.push(format!("equal to {} significant digits", matching))
+                }
+                (None, Some(differing)) => {
+                    differences.push(format!("not equal to {} significant digits", differing))
+                }
+                (None, None) => {}
+            }
+        } else if self.order_matches == Some(false) {
+            differences.push(String::from("same rows, different order"));
+        }
+        if self.row_counts.0 != self.row_counts.1 {
+            differences.push(format!(
+                "row counts differ ({} vs {})",
+                self.row_counts.0, self.row_counts.1
+            ));
+        }
+        if !self.differing_columns.is_empty() {
+            let names: Vec<String> = self
+                .differing_columns
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect();
+            differences.push(format!("columns {} differ", names.join(", ")));
+        }
+        write!(f, "{}", differences.join(", "))
+    }
+}
@@ -1,14 +1,26 @@
-use fasthash::murmur3::hash128;
 use std::sync::Arc;
 
-use crate::{config::IdHashConfig, unf_vector::UNFVector, utils::ThreadArrayChunk};
+use crate::{
+    config::{ColumnOrder, IdHashConfig, MultisetHash, Version},
+    encoding::{encode_column, encode_key, is_encodable},
+    fingerprint::FingerprintError,
+    hyperloglog::HyperLogLog,
+    iblt::Iblt,
+    minhash::MinHash,
+    multiset::LtHash,
+    schema::is_numeric,
+    summary::{column_bounds, merge_bounds, Bounds},
+    unf6::has_unf6_form,
+    unf_vector::UNFVector,
+    utils::ThreadArrayChunk,
+};
 use arrow::{
     array::{
-        BooleanArray, Float32Array, Float64Array, Int32Array, Int64Array, UInt16Array, UInt32Array,
-        UInt64Array, Utf8Array,
+        BooleanArray, Float32Array, Float64Array, Int128Array, Int16Array, Int32Array, Int64Array,
+        Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array, Utf8Array,
     },
     chunk::Chunk,
-    datatypes::Schema,
+    datatypes::{DataType, Schema},
 };
 
 use arrow::datatypes::TimeUnit;
@@ -26,70 +38,129 @@ where
     }
 }
 
+/// Numbers of every type in their UNF version 6 representation
+fn convert_numeric_col_to_raw<'a>(
+    col: &'a dyn std::any::Any,
+    data_type: &DataType,
+    characters: usize,
+    digits: usize,
+) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+    match data_type {
+        DataType::Int8 => col
+            .downcast_ref::<Int8Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::Int16 => col
+            .downcast_ref::<Int16Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::Int32 => col
+            .downcast_ref::<Int32Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::Int64 => col
+            .downcast_ref::<Int64Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::UInt8 => col
+            .downcast_ref::<UInt8Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::UInt16 => col
+            .downcast_ref::<UInt16Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::UInt32 => col
+            .downcast_ref::<UInt32Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::UInt64 => col
+            .downcast_ref::<UInt64Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::Float32 => col
+            .downcast_ref::<Float32Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::Float64 => col
+            .downcast_ref::<Float64Array>()
+            .expect("Failed to Downcast")
+            .raw_unf6(characters, digits),
+        DataType::Decimal(_, _) => col
+            .downcast_ref::<Int128Array>()
+            .expect("Failed to downcast Decimal to Int128")
+            .raw_unf6(characters, digits),
+        other => unreachable!("{:?} is not numeric", other),
+    }
+}
+
 fn convert_col_to_raw<'a>(
     col: &'a dyn std::any::Any,
     column_index: usize,
     schema: &Arc<Schema>,
     is_null: bool,
-    config: IdHashConfig,
+    config: &IdHashConfig,
 ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
-    match (*schema.fields)[column_index].data_type() {
+    let field = &schema.fields[column_index];
+    let characters = config.characters_for(&field.name);
+    let digits = config.digits_for(&field.name);
+    if config.numeric_invariant && is_numeric(field.data_type()) {
+        return convert_numeric_col_to_raw(col, field.data_type(), characters, digits);
+    }
+    match field.data_type() {
         arrow::datatypes::DataType::Null => todo!(),
         arrow::datatypes::DataType::Boolean => col
             .downcast_ref::<BooleanArray>()
             .expect("Failed to downcast to Bool")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Int8 => col
-            .downcast_ref::<Int32Array>()
+            .downcast_ref::<Int8Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Int16 => col
-            .downcast_ref::<Int32Array>()
+            .downcast_ref::<Int16Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Int32 => col
             .downcast_ref::<Int32Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Int64 => col
             .downcast_ref::<Int64Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::UInt8 => col
-            .downcast_ref::<UInt16Array>()
+            .downcast_ref::<UInt8Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::UInt16 => col
             .downcast_ref::<UInt16Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::UInt32 => col
             .downcast_ref::<UInt32Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::UInt64 => col
             .downcast_ref::<UInt64Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
-        arrow::datatypes::DataType::Float16 => col
-            .downcast_ref::<Float32Array>()
-            .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
+        arrow::datatypes::DataType::Float16 => todo!(),
         arrow::datatypes::DataType::Float32 => col
             .downcast_ref::<Float32Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Float64 => col
             .downcast_ref::<Float64Array>()
             .expect("Failed to Downcast")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Timestamp(TimeUnit::Microsecond, _)
         | arrow::datatypes::DataType::Timestamp(TimeUnit::Millisecond, _)
         | arrow::datatypes::DataType::Timestamp(TimeUnit::Nanosecond, _)
         | arrow::datatypes::DataType::Timestamp(TimeUnit::Second, _) => col
             .downcast_ref::<Int64Array>()
             .expect("Failed to Downcast to Int64Array")
-            .raw(12, config.digits, is_null),
+            .raw(12, digits, is_null),
         arrow::datatypes::DataType::Time32(_) => todo!(),
         arrow::datatypes::DataType::Time64(_) => todo!(),
         arrow::datatypes::DataType::Duration(_) => todo!(),
@@ -103,52 +174,409 @@ fn convert_col_to_raw<'a>(
         arrow::datatypes::DataType::Struct(_) => todo!(),
         arrow::datatypes::DataType::Union(_, _, _) => todo!(),
         arrow::datatypes::DataType::Dictionary(_, _, _) => todo!(),
-        arrow::datatypes::DataType::Decimal(_, _) => todo!(),
+        arrow::datatypes::DataType::Decimal(_, _) => col
+            .downcast_ref::<Int128Array>()
+            .expect("Failed to downcast Decimal to Int128")
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Map(_, _) => todo!(),
         arrow::datatypes::DataType::Extension(_, _, _) => todo!(),
         arrow::datatypes::DataType::Date32 => col
             .downcast_ref::<Int32Array>()
             .expect("Failed to downcast Date to Int32")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Date64 => col
             .downcast_ref::<Int64Array>()
             .expect("Failed to downcast Date to Int64")
-            .raw(config.characters, config.digits, is_null),
+            .raw(characters, digits, is_null),
         arrow::datatypes::DataType::Utf8 => col
             .downcast_ref::<Utf8Array<i32>>()
             .expect("Failed to downcast to Utf-8")
-            .raw(config.characters, config.digits, is_null),
+            .raw_normalized(
+                characters,
+                digits,
+                is_null,
+                config.string_normalizers_for(&field.name),
+            ),
         arrow::datatypes::DataType::LargeUtf8 => todo!(),
     }
 }
 
+/// Whether values of a type can be hashed in the configured row encoding
+fn is_hashable(data_type: &DataType, config: &IdHashConfig) -> bool {
+    match config.version {
+        Version::V1 => matches!(
+            data_type,
+            DataType::Boolean
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::Decimal(_, _)
+                | DataType::Date32
+                | DataType::Date64
+                | DataType::Timestamp(_, _)
+                | DataType::Utf8
+        ),
+        Version::V2 => is_encodable(data_type, config),
+    }
+}
+
+/// Check every column of the Schema can be hashed, so an unsupported type is
+/// reported before any batch is read rather than failing part way through
+pub(crate) fn check_column_types(
+    schema: &Schema,
+    config: &IdHashConfig,
+) -> Result<(), FingerprintError> {
+    match schema.fields.iter().find(|field| {
+        !is_hashable(field.data_type(), config)
+            || (config.unf6 && !has_unf6_form(field.data_type()))
+    }) {
+        Some(field) => Err(FingerprintError::UnsupportedType {
+            column: field.name.clone(),
+            data_type: field.data_type().clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Indices of the Schema's columns, in the order their values are combined into a row
+pub fn column_order(schema: &Schema, order: ColumnOrder) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..schema.fields.len()).collect();
+    if order == ColumnOrder::ByName {
+        indices.sort_by(|&a, &b| schema.fields[a].name.cmp(&schema.fields[b].name));
+    }
+    indices
+}
+
+/// Running totals for one or more RecordBatches
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct HashState {
+    pub data: u128,
+    /// Every lane of a wide hash, starting with `data`, if wider than 128 bits
+    pub lanes: Vec<u128>,
+    /// Order-invariant hash of each column, in Schema order
+    pub columns: Vec<u128>,
+    pub rows: u64,
+    /// Null count of each column, in Schema order
+    pub null_counts: Vec<u64>,
+    /// Minimum and maximum of each column, in Schema order
+    pub bounds: Vec<Bounds>,
+    pub lthash: Option<LtHash>,
+    /// Only meaningful when batches are merged in their original order
+    pub ordered: Option<OrderedHash>,
+    /// Data and column hashes at each extra `digits` level, in the config's order
+    pub resolutions: Vec<ResolutionState>,
+    /// IBLT sketch of the row hashes
+    pub sketch: Option<Iblt>,
+    /// Bottom-k MinHash sketch of the row hashes
+    pub min_hash: Option<MinHash>,
+    /// HyperLogLog sketch of the row hashes
+    pub hyperloglog: Option<HyperLogLog>,
+}
+
+/// Running totals of the data rounded to another number of significant digits
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ResolutionState {
+    pub data: u128,
+    /// Order-invariant hash of each column, in Schema order
+    pub columns: Vec<u128>,
+}
+
+impl ResolutionState {
+    fn merge(self, other: ResolutionState) -> ResolutionState {
+        ResolutionState {
+            data: self.data.wrapping_add(other.data),
+            columns: merge_columns(self.columns, other.columns, u128::wrapping_add),
+        }
+    }
+}
+
+/// Multiplier of the polynomial rolling hash, an odd 128-bit constant
+const ORDER_MULTIPLIER: u128 = 0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835;
+
+/// Polynomial rolling hash over the row hashes, `h = h * P + row`, which
+/// depends on the order of the rows.
+/// Adjacent runs of rows can still be hashed separately and then merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct OrderedHash {
+    pub hash: u128,
+    /// `P` raised to the number of rows hashed so far
+    power: u128,
+}
+
+impl Default for OrderedHash {
+    fn default() -> Self {
+        OrderedHash { hash: 0, power: 1 }
+    }
+}
+
+impl OrderedHash {
+    pub fn push(&mut self, row_hash: u128) {
+        self.hash = self
+            .hash
+            .wrapping_mul(ORDER_MULTIPLIER)
+            .wrapping_add(row_hash);
+        self.power = self.power.wrapping_mul(ORDER_MULTIPLIER);
+    }
+
+    /// Hash of the rows of `self` followed by the rows of `next`
+    pub fn then(self, next: OrderedHash) -> OrderedHash {
+        OrderedHash {
+            hash: self.hash.wrapping_mul(next.power).wrapping_add(next.hash),
+            power: self.power.wrapping_mul(next.power),
+        }
+    }
+}
+
+/// Combine two per-column vectors, where an empty vector is the identity
+fn merge_columns<T>(left: Vec<T>, right: Vec<T>, merge: impl Fn(T, T) -> T) -> Vec<T> {
+    if left.is_empty() {
+        return right;
+    }
+    if right.is_empty() {
+        return left;
+    }
+    left.into_iter()
+        .zip(right)
+        .map(|(acc, x)| merge(acc, x))
+        .collect()
+}
+
+impl HashState {
+    /// Combine the totals of two disjoint sets of rows, where the rows of
+    /// `other` follow the rows of `self`
+    pub fn merge(self, other: HashState) -> HashState {
+        HashState {
+            data: self.data.wrapping_add(other.data),
+            lanes: merge_columns(self.lanes, other.lanes, u128::wrapping_add),
+            columns: merge_columns(self.columns, other.columns, u128::wrapping_add),
+            rows: self.rows + other.rows,
+            null_counts: merge_columns(self.null_counts, other.null_counts, |acc, x| acc + x),
+            bounds: merge_columns(self.bounds, other.bounds, merge_bounds),
+            lthash: match (self.lthash, other.lthash) {
+                (Some(left), Some(right)) => Some(
+                    left.merge(&right)
+                        .expect("Multisets of one config have the same key"),
+                ),
+                (lthash, None) | (None, lthash) => lthash,
+            },
+            ordered: match (self.ordered, other.ordered) {
+                (Some(first), Some(next)) => Some(first.then(next)),
+                (ordered, None) | (None, ordered) => ordered,
+            },
+            resolutions: merge_columns(self.resolutions, other.resolutions, ResolutionState::merge),
+            sketch: match (self.sketch, other.sketch) {
+                (Some(left), Some(right)) => Some(
+                    left.merge(&right)
+                        .expect("Sketches of one config have the same size"),
+                ),
+                (sketch, None) | (None, sketch) => sketch,
+            },
+            min_hash: match (self.min_hash, other.min_hash) {
+                (Some(left), Some(right)) => Some(left.merge(&right)),
+                (min_hash, None) | (None, min_hash) => min_hash,
+            },
+            hyperloglog: match (self.hyperloglog, other.hyperloglog) {
+                (Some(left), Some(right)) => Some(
+                    left.merge(&right)
+                        .expect("Sketches of one config have the same precision"),
+                ),
+                (hyperloglog, None) | (None, hyperloglog) => hyperloglog,
+            },
+        }
+    }
+}
+
 /// Produce MurmurHash for a given RecordBatch
 ///
 pub(crate) fn idhash_batch(
     input: Chunk<ThreadArrayChunk>,
     schema: &Arc<Schema>,
-    config: IdHashConfig,
-) -> u128 {
+    config: &IdHashConfig,
+) -> HashState {
+    if input.is_empty() {
+        return HashState::default();
+    }
+    let columns = input.columns();
+    let order = column_order(schema, config.column_order);
+    let keys: Vec<&str> = order
+        .iter()
+        .map(|&col_index| match config.column_order {
+            ColumnOrder::Positional => "",
+            ColumnOrder::ByName => schema.fields[col_index].name.as_str(),
+        })
+        .collect();
+    let mut lanes = vec![0; config.width.lanes()];
+    let mut state = HashState {
+        data: 0,
+        lanes: if lanes.len() > 1 {
+            lanes.clone()
+        } else {
+            vec![]
+        },
+        columns: if config.column_fingerprints {
+            vec![0; columns.len()]
+        } else {
+            vec![]
+        },
+        rows: input.len() as u64,
+        null_counts: columns.iter().map(|col| col.null_count() as u64).collect(),
+        bounds: if config.min_max {
+            columns
+                .iter()
+                .zip(schema.fields.iter())
+                .map(|(col, field)| column_bounds(col.as_ref(), field.data_type()))
+                .collect()
+        } else {
+            vec![]
+        },
+        lthash: (config.multiset == MultisetHash::LtHash)
+            .then(|| LtHash::new(config.hasher.lthash_key())),
+        ordered: config.order_sensitive.then(OrderedHash::default),
+        resolutions: vec![],
+        sketch: config.sketch.map(Iblt::new),
+        min_hash: config.min_hash.map(MinHash::new),
+        hyperloglog: config.hyperloglog.map(HyperLogLog::new),
+    };
+    let mut row_bytes = Vec::new();
+    for row in encoded_rows(&input, schema, &order, config) {
+        serialize_row(&row, &keys, config, &mut row_bytes);
+        let row_hash = if state.lanes.is_empty() {
+            config.hasher.hash(&row_bytes)
+        } else {
+            config.hasher.hash_lanes(&row_bytes, &mut lanes);
+            for (acc, x) in state.lanes.iter_mut().zip(lanes.iter()) {
+                *acc = acc.wrapping_add(*x);
+            }
+            lanes[0]
+        };
+        state.data = state.data.wrapping_add(row_hash);
+        if let Some(ordered) = state.ordered.as_mut() {
+            ordered.push(row_hash);
+        }
+        if let Some(lthash) = state.lthash.as_mut() {
+            lthash.insert(&row_bytes);
+        }
+        if let Some(sketch) = state.sketch.as_mut() {
+            sketch.insert(row_hash);
+        }
+        if let Some(min_hash) = state.min_hash.as_mut() {
+            min_hash.insert(row_hash);
+        }
+        if let Some(hyperloglog) = state.hyperloglog.as_mut() {
+            hyperloglog.insert(row_hash);
+        }
+        if config.column_fingerprints {
+            for (&col_index, value) in order.iter().zip(row.iter()) {
+                state.columns[col_index] =
+                    state.columns[col_index].wrapping_add(config.hasher.hash(value));
+            }
+        }
+    }
+    state.resolutions = config
+        .resolutions
+        .iter()
+        .map(|&digits| {
+            let config = config
+                .with_digits(digits)
+                .expect("resolutions are checked when the config is built");
+            let mut resolution = ResolutionState {
+                data: 0,
+                columns: vec![0; state.columns.len()],
+            };
+            for row in encoded_rows(&input, schema, &order, &config) {
+                serialize_row(&row, &keys, &config, &mut row_bytes);
+                resolution.data = resolution.data.wrapping_add(config.hasher.hash(&row_bytes));
+                if config.column_fingerprints {
+                    for (&col_index, value) in order.iter().zip(row.iter()) {
+                        resolution.columns[col_index] =
+                            resolution.columns[col_index].wrapping_add(config.hasher.hash(value));
+                    }
+                }
+            }
+            resolution
+        })
+        .collect();
+    state
+}
+
+/// Hash of each row of a batch, as added to the data hash and the sketch
+pub(crate) fn row_hashes(
+    input: &Chunk<ThreadArrayChunk>,
+    schema: &Arc<Schema>,
+    config: &IdHashConfig,
+) -> Vec<u128> {
+    if input.is_empty() {
+        return vec![];
+    }
+    let order = column_order(schema, config.column_order);
+    let keys: Vec<&str> = order
+        .iter()
+        .map(|&col_index| match config.column_order {
+            ColumnOrder::Positional => "",
+            ColumnOrder::ByName => schema.fields[col_index].name.as_str(),
+        })
+        .collect();
+    let mut row_bytes = Vec::new();
+    encoded_rows(input, schema, &order, config)
+        .map(|row| {
+            serialize_row(&row, &keys, config, &mut row_bytes);
+            config.hasher.hash(&row_bytes)
+        })
+        .collect()
+}
+
+/// Encoded values of each row, with columns in `order`
+fn encoded_rows<'a>(
+    input: &'a Chunk<ThreadArrayChunk>,
+    schema: &Arc<Schema>,
+    order: &[usize],
+    config: &IdHashConfig,
+) -> HashIterator<Box<dyn Iterator<Item = Vec<u8>> + 'a>> {
+    let columns = input.columns();
     // To progress row-wise, collect all Columns into Iterators, then progress
     // each iterator one at a time.
     // https://stackoverflow.com/a/55292215
     HashIterator(
-        input
-            .columns()
+        order
             .iter()
-            .enumerate()
-            .map(|(col_index, col)| {
-                convert_col_to_raw(
-                    col.as_any(),
-                    col_index,
-                    schema,
-                    col.null_count() > 0,
-                    config,
-                )
+            .map(|&col_index| {
+                let col = &columns[col_index];
+                match config.version {
+                    Version::V1 => convert_col_to_raw(
+                        col.as_any(),
+                        col_index,
+                        schema,
+                        col.null_count() > 0,
+                        config,
+                    ),
+                    Version::V2 => encode_column(col.as_ref(), &schema.fields[col_index], config),
+                }
             })
             .collect(),
     )
-    .map(|row| hash128(row.into_iter().flatten().collect::<Vec<u8>>()))
-    .reduce(|acc, x| acc.wrapping_add(x))
-    .unwrap()
+}
+
+/// Concatenate the encoded values of a row into `row_bytes`, keyed by column
+/// name when columns are ordered by name
+fn serialize_row(row: &[Vec<u8>], keys: &[&str], config: &IdHashConfig, row_bytes: &mut Vec<u8>) {
+    row_bytes.clear();
+    for (position, value) in row.iter().enumerate() {
+        if config.column_order == ColumnOrder::ByName {
+            match config.version {
+                Version::V1 => {
+                    row_bytes.extend_from_slice(keys[position].as_bytes());
+                    row_bytes.push(b'\x00');
+                }
+                Version::V2 => encode_key(keys[position], row_bytes),
+            }
+        }
+        row_bytes.extend_from_slice(value);
+    }
 }
@@ -0,0 +1,172 @@
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+/// Identifies a serialized sketch, followed by the format version and the
+/// settings id
+const MAGIC: &[u8; 4] = b"HYLL";
+const FORMAT_VERSION: u8 = 1;
+
+/// Smallest and largest supported precision
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 18;
+
+/// Errors from combining sketches
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HyperLogLogError {
+    /// Sketches of different precisions have different registers
+    PrecisionMismatch(u8, u8),
+    /// Sketches written with different settings hash the same rows differently
+    SettingsMismatch,
+}
+
+impl fmt::Display for HyperLogLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HyperLogLogError::PrecisionMismatch(left, right) => {
+                write!(f, "sketches have precisions {} and {}", left, right)
+            }
+            HyperLogLogError::SettingsMismatch => write!(
+                f,
+                "sketches were written with different settings, e.g. version or hasher"
+            ),
+        }
+    }
+}
+
+impl Error for HyperLogLogError {}
+
+/// HyperLogLog sketch of the row hashes, estimating the number of distinct rows.
+///
+/// `2^precision` one-byte registers each keep the longest run of leading zeros
+/// among the row hashes assigned to them, for a standard error of about
+/// `1.04 / sqrt(2^precision)`. Sketches of batches, threads or machines are
+/// merged by taking the maximum of each register, provided they share a precision
+/// and settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HyperLogLog {
+    /// `IdHashConfig::settings_id` of the config the row hashes come from
+    settings: u64,
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// # Panics
+    /// If the precision is outside `MIN_PRECISION..=MAX_PRECISION`
+    pub fn new(precision: u8) -> Self {
+        assert!(
+            (MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+            "HyperLogLog precision must be between {} and {}",
+            MIN_PRECISION,
+            MAX_PRECISION
+        );
+        HyperLogLog {
+            settings: 0,
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// The same sketch, recording the settings its row hashes were computed with
+    pub fn with_settings(mut self, settings: u64) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn settings(&self) -> u64 {
+        self.settings
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Add a row hash
+    pub fn insert(&mut self, hash: u128) {
+        let x = (hash as u64) ^ ((hash >> 64) as u64);
+        let index = (x >> (64 - self.precision)) as usize;
+        let rank = ((x << self.precision).leading_zeros() + 1).min(65 - self.precision as u32);
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    /// Sketch of the union of the rows of two sketches of the same precision
+    pub fn merge(mut self, other: &HyperLogLog) -> Result<HyperLogLog, HyperLogLogError> {
+        if self.precision != other.precision {
+            return Err(HyperLogLogError::PrecisionMismatch(
+                self.precision,
+                other.precision,
+            ));
+        }
+        if self.settings != other.settings {
+            return Err(HyperLogLogError::SettingsMismatch);
+        }
+        for (acc, x) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *acc = (*acc).max(*x);
+        }
+        Ok(self)
+    }
+
+    /// Estimated number of distinct rows
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&register| 2f64.powi(-(register as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|&&register| register == 0)
+            .count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate for small cardinalities
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+
+    /// Compact binary form, which can be stored and later merged
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 10 + self.registers.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.settings.to_le_bytes());
+        bytes.push(self.precision);
+        bytes.extend_from_slice(&self.registers);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<HyperLogLog> {
+        let body = bytes.strip_prefix(MAGIC.as_slice())?;
+        let (&version, body) = body.split_first()?;
+        if version != FORMAT_VERSION || body.len() < 8 {
+            return None;
+        }
+        let (settings, body) = body.split_at(8);
+        let settings = u64::from_le_bytes(settings.try_into().ok()?);
+        let (&precision, registers) = body.split_first()?;
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision)
+            || registers.len() != 1 << precision
+        {
+            return None;
+        }
+        if registers.iter().any(|&register| register > 65 - precision) {
+            return None;
+        }
+        Some(HyperLogLog {
+            settings,
+            precision,
+            registers: registers.to_vec(),
+        })
+    }
+}
@@ -0,0 +1,303 @@
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+/// Number of cells each row hash is added to, one in each part of the table
+const HASH_COUNT: usize = 3;
+
+/// Identifies a serialized sketch, followed by the format version and the
+/// settings id
+const MAGIC: &[u8; 4] = b"IBLT";
+const FORMAT_VERSION: u8 = 1;
+
+/// Largest number of high bits of a row hash that may be lost when dividing
+/// its sum by its number of occurrences, i.e. rows occurring a multiple of
+/// 2^11 more times on one side cannot be recovered
+const MAX_LOST_BITS: u32 = 10;
+
+/// Bytes of a serialized cell: count, sum of row hashes and sum of checksums
+const CELL_BYTES: usize = 8 + 16 + 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Cell {
+    count: i64,
+    key_sum: u128,
+    check_sum: u64,
+}
+
+impl Cell {
+    /// Add `count` occurrences of a row hash, or remove them if negative
+    fn add(&mut self, key: u128, count: i64) {
+        self.count = self.count.wrapping_add(count);
+        self.key_sum = self
+            .key_sum
+            .wrapping_add(key.wrapping_mul(count as i128 as u128));
+        self.check_sum = self
+            .check_sum
+            .wrapping_add(checksum(key).wrapping_mul(count as u64));
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Cell::default()
+    }
+
+    /// The single row hash left in the cell and its number of extra
+    /// occurrences, negative when they come from the subtracted side, if any.
+    ///
+    /// A row hash occurring `k` times leaves `k` times the hash and its checksum.
+    /// The hash is recovered by dividing by `k`, which loses as many high bits
+    /// as `k` has factors of 2, so those candidates are each checked.
+    fn pure(&self) -> Option<(u128, i64)> {
+        let (key_sum, check_sum) = match self.count {
+            0 => return None,
+            count if count > 0 => (self.key_sum, self.check_sum),
+            _ => (self.key_sum.wrapping_neg(), self.check_sum.wrapping_neg()),
+        };
+        let copies = self.count.unsigned_abs();
+        let twos = copies.trailing_zeros();
+        if twos > MAX_LOST_BITS || key_sum.trailing_zeros() < twos {
+            return None;
+        }
+        let low = (key_sum >> twos).wrapping_mul(odd_inverse(copies as u128 >> twos));
+        let known = u128::MAX.checked_shr(twos).unwrap_or(0);
+        (0..1u128 << twos)
+            .map(|high| (low & known) | high.checked_shl(128 - twos).unwrap_or(0))
+            .find(|&key| check_sum == checksum(key).wrapping_mul(copies))
+            .map(|key| (key, self.count))
+    }
+}
+
+/// Inverse of an odd number modulo 2^128, by Newton's iteration, each step
+/// doubling the number of correct low bits from the 3 of `x` itself
+fn odd_inverse(x: u128) -> u128 {
+    let mut inverse = x;
+    for _ in 0..6 {
+        inverse = inverse.wrapping_mul(2u128.wrapping_sub(x.wrapping_mul(inverse)));
+    }
+    inverse
+}
+
+/// SplitMix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn mix_key(key: u128, seed: u64) -> u64 {
+    mix((key as u64) ^ mix(((key >> 64) as u64) ^ seed))
+}
+
+fn checksum(key: u128) -> u64 {
+    mix_key(key, 0x2545_f491_4f6c_dd1d)
+}
+
+/// Errors from combining or decoding sketches
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IbltError {
+    /// Sketches of different sizes cannot be merged or subtracted
+    SizeMismatch(usize, usize),
+    /// Sketches written with different settings hash the same rows differently
+    SettingsMismatch,
+    /// Too many rows differ for the size of the sketch
+    Undecodable,
+}
+
+impl fmt::Display for IbltError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IbltError::SizeMismatch(left, right) => {
+                write!(f, "sketches have {} and {} cells", left, right)
+            }
+            IbltError::SettingsMismatch => write!(
+                f,
+                "sketches were written with different settings, e.g. version or hasher"
+            ),
+            IbltError::Undecodable => {
+                write!(f, "too many rows differ to decode, use a larger sketch")
+            }
+        }
+    }
+}
+
+impl Error for IbltError {}
+
+/// Row hashes present in only one of two datasets
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SketchDifference {
+    /// Row hashes of the sketch subtracted from, once per extra occurrence
+    pub left_only: Vec<u128>,
+    /// Row hashes of the subtracted sketch, once per extra occurrence
+    pub right_only: Vec<u128>,
+}
+
+/// Invertible Bloom lookup table of row hashes.
+///
+/// Each row hash is added to one cell in each of three parts of the table.
+/// Subtracting the sketch of another dataset cancels the rows both have in
+/// common, after which up to roughly `cells / 1.3` differing row hashes can be
+/// listed exactly, however large the datasets are. Like the data hash, it is
+/// independent of row order and sketches of batches can be merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Iblt {
+    /// `IdHashConfig::settings_id` of the config the row hashes come from
+    settings: u64,
+    cells: Vec<Cell>,
+}
+
+impl Iblt {
+    /// An empty sketch of at least `cells` cells, rounded up to a multiple of 3
+    pub fn new(cells: usize) -> Self {
+        let cells = (cells.max(1) + HASH_COUNT - 1) / HASH_COUNT * HASH_COUNT;
+        Iblt {
+            settings: 0,
+            cells: vec![Cell::default(); cells],
+        }
+    }
+
+    /// The same sketch, recording the settings its row hashes were computed with
+    pub fn with_settings(mut self, settings: u64) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn settings(&self) -> u64 {
+        self.settings
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.iter().all(Cell::is_empty)
+    }
+
+    fn indices(&self, key: u128) -> [usize; HASH_COUNT] {
+        let part = self.cells.len() / HASH_COUNT;
+        let mut indices = [0; HASH_COUNT];
+        for (seed, index) in indices.iter_mut().enumerate() {
+            *index = seed * part + (mix_key(key, seed as u64) % part as u64) as usize;
+        }
+        indices
+    }
+
+    fn add(&mut self, key: u128, count: i64) {
+        for index in self.indices(key) {
+            self.cells[index].add(key, count);
+        }
+    }
+
+    /// Add a row hash
+    pub fn insert(&mut self, key: u128) {
+        self.add(key, 1);
+    }
+
+    /// Sketches can only be combined cell by cell if they have the same cells
+    /// and hash rows the same way
+    fn check_compatible(&self, other: &Iblt) -> Result<(), IbltError> {
+        if self.len() != other.len() {
+            return Err(IbltError::SizeMismatch(self.len(), other.len()));
+        }
+        if self.settings != other.settings {
+            return Err(IbltError::SettingsMismatch);
+        }
+        Ok(())
+    }
+
+    /// Union of the rows of two sketches of the same size
+    pub fn merge(mut self, other: &Iblt) -> Result<Iblt, IbltError> {
+        self.check_compatible(other)?;
+        for (acc, x) in self.cells.iter_mut().zip(other.cells.iter()) {
+            acc.count = acc.count.wrapping_add(x.count);
+            acc.key_sum = acc.key_sum.wrapping_add(x.key_sum);
+            acc.check_sum = acc.check_sum.wrapping_add(x.check_sum);
+        }
+        Ok(self)
+    }
+
+    /// Remove the rows of `other`, leaving the rows only one side has
+    pub fn subtract(mut self, other: &Iblt) -> Result<Iblt, IbltError> {
+        self.check_compatible(other)?;
+        for (acc, x) in self.cells.iter_mut().zip(other.cells.iter()) {
+            acc.count = acc.count.wrapping_sub(x.count);
+            acc.key_sum = acc.key_sum.wrapping_sub(x.key_sum);
+            acc.check_sum = acc.check_sum.wrapping_sub(x.check_sum);
+        }
+        Ok(self)
+    }
+
+    /// List the row hashes of a subtracted sketch, by repeatedly removing a
+    /// row hash from a cell holding only that one
+    pub fn decode(mut self) -> Result<SketchDifference, IbltError> {
+        let mut difference = SketchDifference::default();
+        let mut decoded = 0;
+        let mut queue: Vec<usize> = (0..self.cells.len()).collect();
+        while let Some(index) = queue.pop() {
+            let Some((key, count)) = self.cells[index].pure() else {
+                continue;
+            };
+            decoded += 1;
+            if decoded > self.cells.len() {
+                // A checksum collision made a cell look pure
+                return Err(IbltError::Undecodable);
+            }
+            let side = if count > 0 {
+                &mut difference.left_only
+            } else {
+                &mut difference.right_only
+            };
+            side.extend(std::iter::repeat(key).take(count.unsigned_abs() as usize));
+            let indices = self.indices(key);
+            self.add(key, -count);
+            queue.extend(indices);
+        }
+        if !self.is_empty() {
+            return Err(IbltError::Undecodable);
+        }
+        difference.left_only.sort_unstable();
+        difference.right_only.sort_unstable();
+        Ok(difference)
+    }
+
+    /// Compact binary form, which can be stored and later subtracted
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 13 + self.cells.len() * CELL_BYTES);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.settings.to_le_bytes());
+        bytes.extend_from_slice(&(self.cells.len() as u32).to_le_bytes());
+        for cell in &self.cells {
+            bytes.extend_from_slice(&cell.count.to_le_bytes());
+            bytes.extend_from_slice(&cell.key_sum.to_le_bytes());
+            bytes.extend_from_slice(&cell.check_sum.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Iblt> {
+        let body = bytes.strip_prefix(MAGIC.as_slice())?;
+        let (&version, body) = body.split_first()?;
+        if version != FORMAT_VERSION || body.len() < 12 {
+            return None;
+        }
+        let (settings, body) = body.split_at(8);
+        let settings = u64::from_le_bytes(settings.try_into().ok()?);
+        let (len, body) = body.split_at(4);
+        let len = u32::from_le_bytes(len.try_into().ok()?) as usize;
+        if len == 0 || len % HASH_COUNT != 0 || body.len() != len * CELL_BYTES {
+            return None;
+        }
+        let cells = body
+            .chunks_exact(CELL_BYTES)
+            .map(|cell| Cell {
+                count: i64::from_le_bytes(cell[..8].try_into().unwrap()),
+                key_sum: u128::from_le_bytes(cell[8..24].try_into().unwrap()),
+                check_sum: u64::from_le_bytes(cell[24..].try_into().unwrap()),
+            })
+            .collect();
+        Some(Iblt { settings, cells })
+    }
+}
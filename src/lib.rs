@@ -1,51 +1,291 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
-use arrow::{chunk::Chunk, datatypes::Schema};
+use arrow::{array::get_display, chunk::Chunk, datatypes::Schema};
 
-use config::{IdHashConfig, IdHashConfigBuilder};
-use hash_builder::idhash_batch;
+use config::IdHashConfig;
+use filter::{BoundFilter, FilterError};
+use hash_builder::{check_column_types, idhash_batch, row_hashes, HashState};
+use nulls::apply_null_tokens;
+use unf6::Unf6Builder;
 
 pub mod config;
+pub mod encoding;
+pub mod filter;
+pub mod fingerprint;
 pub mod hash_builder;
+pub mod hyperloglog;
+pub mod iblt;
+pub mod mapping;
+pub mod minhash;
+pub mod multiset;
+pub mod normalize;
+pub mod nulls;
+pub mod row_hasher;
+pub mod schema;
+pub mod selection;
+pub mod summary;
+pub mod tolerance;
+pub mod unf6;
 pub mod unf_vector;
 pub mod utils;
 
+pub use fingerprint::{ColumnFingerprint, Comparison, Fingerprint, FingerprintError, Resolution};
 use rayon::prelude::*;
 pub use utils::ThreadArrayChunk;
+use utils::{project_chunk, project_schema};
+
+/// Narrow the Schema to the columns selected in the config and apply the column
+/// mapping, returning the indices to project each batch onto, or `None` if the
+/// columns are hashed as they are
+fn select_columns(schema: Arc<Schema>, config: &IdHashConfig) -> (Arc<Schema>, Option<Vec<usize>>) {
+    if config.selection.is_all() && config.mapping.is_identity() {
+        return (schema, None);
+    }
+    let indices = config
+        .mapping
+        .arrange(&schema, config.selection.resolve(&schema));
+    let projected = config.mapping.rename(&project_schema(&schema, &indices));
+    (projected, Some(indices))
+}
+
+/// Resolve the row filter in the config against the unprojected Schema,
+/// so rows can be filtered on columns that are not hashed
+fn bind_filter(schema: &Schema, config: &IdHashConfig) -> Result<Option<BoundFilter>, FilterError> {
+    if config.filter.is_all() {
+        return Ok(None);
+    }
+    config.filter.bind(schema).map(Some)
+}
+
+/// Steps applied to each batch before it is hashed, in order: null tokens are
+/// replaced, rows are filtered, then columns are selected
+struct BatchPipeline {
+    /// Schema of the input batches
+    input_schema: Arc<Schema>,
+    null_tokens: Vec<String>,
+    filter: Option<BoundFilter>,
+    projection: Option<Vec<usize>>,
+}
+
+impl BatchPipeline {
+    /// The pipeline for a Schema, and the Schema of the batches it produces
+    fn new(
+        schema: Arc<Schema>,
+        config: &IdHashConfig,
+    ) -> Result<(Self, Arc<Schema>), FingerprintError> {
+        let filter = bind_filter(&schema, config)?;
+        let (output_schema, projection) = select_columns(schema.clone(), config);
+        if output_schema.fields.is_empty() {
+            // An empty dataset would hash to 0, which looks like a valid fingerprint
+            return Err(FingerprintError::NoColumns);
+        }
+        check_column_types(&output_schema, config)?;
+        let pipeline = BatchPipeline {
+            input_schema: schema,
+            null_tokens: config.null_tokens.clone(),
+            filter,
+            projection,
+        };
+        Ok((pipeline, output_schema))
+    }
+
+    fn apply(&self, batch: Chunk<ThreadArrayChunk>) -> Chunk<ThreadArrayChunk> {
+        let batch = if self.null_tokens.is_empty() {
+            batch
+        } else {
+            apply_null_tokens(batch, &self.input_schema, &self.null_tokens)
+        };
+        let batch = match &self.filter {
+            Some(filter) => filter.apply(batch),
+            None => batch,
+        };
+        match &self.projection {
+            Some(indices) => project_chunk(batch, indices),
+            None => batch,
+        }
+    }
+}
 
 /// Calculate Identifiable Hash for a series of RecordBatches
-pub fn calculate_idhash<I>(batch_input: I, schema: Arc<Schema>, config: IdHashConfig) -> u128
+pub fn calculate_idhash<I>(
+    batch_input: I,
+    schema: Arc<Schema>,
+    config: IdHashConfig,
+) -> Result<u128, FingerprintError>
 where
     I: Iterator<Item = Chunk<ThreadArrayChunk>>,
 {
-    batch_input
-        .map(|batch| idhash_batch(batch, &schema, config))
-        .reduce(|acc: u128, x: u128| acc.wrapping_add(x))
-        .unwrap()
+    calculate_fingerprint(batch_input, schema, config).map(|fingerprint| fingerprint.combined())
 }
 
 /// Calculate Identifiable Hash for a series of RecordBatches
-pub fn calculate_idhash_par<I>(batch_input: I, schema: Arc<Schema>, config: IdHashConfig) -> u128
+pub fn calculate_idhash_par<I>(
+    batch_input: I,
+    schema: Arc<Schema>,
+    config: IdHashConfig,
+) -> Result<u128, FingerprintError>
 where
     I: ParallelIterator<Item = Chunk<ThreadArrayChunk>>,
 {
-    batch_input
+    calculate_fingerprint_par(batch_input, schema, config).map(|fingerprint| fingerprint.combined())
+}
+
+/// Calculate the Fingerprint for a series of RecordBatches
+///
+/// # Errors
+/// If the config does not apply to the Schema, e.g. a row filter names a
+/// missing column. Nothing is read from `batch_input` in that case.
+pub fn calculate_fingerprint<I>(
+    batch_input: I,
+    schema: Arc<Schema>,
+    config: IdHashConfig,
+) -> Result<Fingerprint, FingerprintError>
+where
+    I: Iterator<Item = Chunk<ThreadArrayChunk>>,
+{
+    let (pipeline, schema) = BatchPipeline::new(schema, &config)?;
+    let mut unf = config.unf6.then(|| Unf6Builder::new(&schema));
+    let state = batch_input
+        .map(|batch| {
+            let batch = pipeline.apply(batch);
+            if let Some(unf) = unf.as_mut() {
+                unf.update(&batch, &schema, &config);
+            }
+            idhash_batch(batch, &schema, &config)
+        })
+        .fold(HashState::default(), HashState::merge);
+    let unf = unf.map(|unf| unf.finish(&schema, &config));
+    Ok(Fingerprint::new(state, unf, &schema, &config))
+}
+
+/// Calculate the Fingerprint for a series of RecordBatches
+///
+/// Batches may arrive in any order, so UNF values and the order-sensitive hash
+/// are not computed.
+///
+/// # Errors
+/// As for `calculate_fingerprint`
+pub fn calculate_fingerprint_par<I>(
+    batch_input: I,
+    schema: Arc<Schema>,
+    config: IdHashConfig,
+) -> Result<Fingerprint, FingerprintError>
+where
+    I: ParallelIterator<Item = Chunk<ThreadArrayChunk>>,
+{
+    let (pipeline, schema) = BatchPipeline::new(schema, &config)?;
+    let state = batch_input
         .into_par_iter()
-        .map(|batch| idhash_batch(batch, &schema, config))
-        .reduce(|| 0, |acc: u128, x: u128| acc.wrapping_add(x))
+        .map(|batch| idhash_batch(pipeline.apply(batch), &schema, &config))
+        .reduce(HashState::default, HashState::merge);
+    let state = HashState {
+        ordered: None,
+        ..state
+    };
+    Ok(Fingerprint::new(state, None, &schema, &config))
 }
 
+/// Find the rows whose hashes are listed, e.g. those recovered from the
+/// difference of two sketches, as `(row hash, values)` pairs in the order they
+/// are read. Values are those of the hashed columns, separated by commas.
+pub fn find_rows<I>(
+    batch_input: I,
+    schema: Arc<Schema>,
+    config: IdHashConfig,
+    hashes: &HashSet<u128>,
+) -> Result<Vec<(u128, String)>, FingerprintError>
+where
+    I: Iterator<Item = Chunk<ThreadArrayChunk>>,
+{
+    let (pipeline, schema) = BatchPipeline::new(schema, &config)?;
+    let mut rows = vec![];
+    for batch in batch_input {
+        let batch = pipeline.apply(batch);
+        let displays: Vec<_> = batch
+            .columns()
+            .iter()
+            .map(|col| get_display(col.as_ref(), ""))
+            .collect();
+        for (index, row_hash) in row_hashes(&batch, &schema, &config).into_iter().enumerate() {
+            if !hashes.contains(&row_hash) {
+                continue;
+            }
+            let values: Vec<String> = displays
+                .iter()
+                .map(|display| {
+                    let mut value = String::new();
+                    display(&mut value, index).expect("Failed to format value");
+                    value
+                })
+                .collect();
+            rows.push((row_hash, values.join(",")));
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
 mod tests {
-    use crate::utils::CSVReader;
+    use crate::config::{
+        ColumnOptions, ColumnOrder, ConfigError, IdHashConfigBuilder, MultisetHash, Version, Width,
+    };
+    use crate::filter::{FilterError, RowFilter};
+    use crate::hyperloglog::{HyperLogLog, HyperLogLogError};
+    use crate::iblt::{Iblt, IbltError};
+    use crate::mapping::ColumnMapping;
+    use crate::minhash::{MinHash, MinHashError};
+    use crate::multiset::{LtHash, LtHashError};
+    use crate::normalize::StringNormalizer;
+    use crate::nulls::common_null_tokens;
+    use crate::row_hasher::row_hasher;
+    use crate::selection::{ColumnSelection, ColumnSelector};
+    use crate::summary::Value;
+    use crate::tolerance::find_tolerance;
+    use crate::unf_vector::unf6_number;
+    use crate::utils::{read_key_file, CSVReader, SchemaInference};
+    use arrow::array::{
+        Array, BinaryArray, Float64Array, Int128Array, Int16Array, Int64Array, Int8Array,
+        UInt16Array, UInt8Array, Utf8Array,
+    };
+    use arrow::datatypes::{DataType, Field};
+    use rayon::iter::ParallelBridge;
 
     use super::*;
 
     fn _read_return_hash(file_path: &str, batch_size: usize) -> u128 {
-        let config = IdHashConfigBuilder::new().build();
+        let config = IdHashConfigBuilder::new().build().unwrap();
         // FIXME: Combining multiple batches is causing an issue.
         let reader = CSVReader::new(file_path.to_string(), 100, batch_size);
         let csv_schema = reader.schema.clone();
-        calculate_idhash(reader, csv_schema, config)
+        calculate_idhash(reader, csv_schema, config).unwrap()
+    }
+
+    /// Fingerprint of a CSV file
+    fn _read_fingerprint(file_path: &str, config: IdHashConfig) -> Fingerprint {
+        let reader = CSVReader::new(file_path.to_string(), 100, 1024);
+        let csv_schema = reader.schema.clone();
+        calculate_fingerprint(reader, csv_schema, config).unwrap()
+    }
+
+    /// Fingerprint of a single batch of named columns
+    fn _arrays_fingerprint(
+        columns: Vec<(&str, Box<dyn Array>)>,
+        config: IdHashConfig,
+    ) -> Fingerprint {
+        let schema = Arc::new(Schema::from(
+            columns
+                .iter()
+                .map(|(name, array)| Field::new(*name, array.data_type().clone(), true))
+                .collect::<Vec<Field>>(),
+        ));
+        let chunk = Chunk::new(
+            columns
+                .into_iter()
+                .map(|(_, array)| ThreadArrayChunk::from(Arc::new(array)))
+                .collect(),
+        );
+        calculate_fingerprint(std::iter::once(chunk), schema, config).unwrap()
     }
 
     #[test]
@@ -74,9 +314,1186 @@ mod tests {
         assert_eq!(reader.into_iter().count(), 2501);
     }
 
+    #[test]
+    fn schema_fingerprint_detects_renamed_column() {
+        let config = IdHashConfigBuilder::new().schema(true).build().unwrap();
+        let original = _read_fingerprint("data/ExampleData.csv", config.clone());
+
+        let reader = CSVReader::new("data/ExampleData.csv".to_string(), 100, 1024);
+        let mut fields = reader.schema.fields.clone();
+        fields[0].name = String::from("renamed");
+        let renamed =
+            calculate_fingerprint(reader, Arc::new(Schema::from(fields)), config).unwrap();
+
+        let comparison = original.compare(&renamed);
+        assert_eq!(comparison.schema_matches, Some(false));
+        assert!(comparison.data_matches);
+        assert_ne!(original.combined(), renamed.combined());
+    }
+
+    #[test]
+    fn column_order_invariant_by_name() {
+        let fingerprint = |file_path: &str, order: ColumnOrder| {
+            let config = IdHashConfigBuilder::new()
+                .schema(true)
+                .column_order(order)
+                .build()
+                .unwrap();
+            _read_fingerprint(file_path, config).combined()
+        };
+        let file_path = "data/ExampleData.csv";
+        let reordered_path = "data/ExampleDataReordered.csv";
+        assert_ne!(
+            fingerprint(file_path, ColumnOrder::Positional),
+            fingerprint(reordered_path, ColumnOrder::Positional)
+        );
+        assert_eq!(
+            fingerprint(file_path, ColumnOrder::ByName),
+            fingerprint(reordered_path, ColumnOrder::ByName)
+        );
+    }
+
+    #[test]
+    fn column_fingerprints_locate_differences() {
+        let config = IdHashConfigBuilder::new()
+            .column_fingerprints(true)
+            .build()
+            .unwrap();
+        let original = _read_fingerprint("data/ExampleDateData.csv", config.clone());
+        assert_eq!(original.columns.len(), 2);
+
+        let modified = _read_fingerprint("data/ExampleDateDataModified.csv", config);
+        let comparison = original.compare(&modified);
+        assert!(!comparison.data_matches);
+        assert_eq!(comparison.differing_columns, vec![String::from("a")]);
+    }
+
+    #[test]
+    fn summary_reports_rows_and_bounds() {
+        let config = IdHashConfigBuilder::new().min_max(true).build().unwrap();
+        let reader = CSVReader::new("data/ExampleDateData.csv".to_string(), 100, 1);
+        let csv_schema = reader.schema.clone();
+        let summary = calculate_fingerprint(reader, csv_schema, config)
+            .unwrap()
+            .summary;
+        assert_eq!(summary.rows, 2);
+        assert_eq!(summary.columns[0].null_count, 0);
+        assert_eq!(summary.columns[0].min, Some(Value::Integer(0)));
+        assert_eq!(summary.columns[0].max, Some(Value::Integer(1)));
+    }
+
+    #[test]
+    fn small_integer_columns_have_bounds_and_filter() {
+        let fingerprint = |filter: &str| {
+            let config = IdHashConfigBuilder::new()
+                .min_max(true)
+                .filter(RowFilter::parse(filter).unwrap())
+                .build()
+                .unwrap();
+            _arrays_fingerprint(
+                vec![
+                    ("a", Box::new(Int8Array::from(&[Some(-3), Some(5), None]))),
+                    (
+                        "b",
+                        Box::new(Int16Array::from(&[Some(300), None, Some(-2)])),
+                    ),
+                    (
+                        "c",
+                        Box::new(UInt8Array::from(&[Some(7), Some(2), Some(9)])),
+                    ),
+                ],
+                config,
+            )
+            .summary
+        };
+        let summary = fingerprint("c >= 0");
+        let bounds: Vec<(Option<Value>, Option<Value>)> = summary
+            .columns
+            .into_iter()
+            .map(|column| (column.min, column.max))
+            .collect();
+        assert_eq!(
+            bounds,
+            vec![
+                (Some(Value::Integer(-3)), Some(Value::Integer(5))),
+                (Some(Value::Integer(-2)), Some(Value::Integer(300))),
+                (Some(Value::UInteger(2)), Some(Value::UInteger(9))),
+            ]
+        );
+        assert_eq!(fingerprint("a < 0 && b > 0 && c >= 7").rows, 1);
+    }
+
+    #[test]
+    fn row_hasher_is_recorded() {
+        let fingerprint = |name: &str| {
+            let config = IdHashConfigBuilder::new()
+                .hasher(row_hasher(name).unwrap())
+                .build()
+                .unwrap();
+            _read_fingerprint("data/ExampleData.csv", config)
+        };
+        let murmur3 = fingerprint("murmur3");
+        assert_eq!(
+            murmur3.data,
+            _read_return_hash("data/ExampleData.csv", 1024)
+        );
+        for name in ["xxh3", "siphash", "blake3"] {
+            let other = fingerprint(name);
+            assert_eq!(other.metadata["hasher"], name);
+            assert_ne!(other.data, murmur3.data);
+            assert_eq!(
+                murmur3.compare(&other).differing_settings,
+                vec![String::from("hasher")]
+            );
+        }
+    }
+
+    #[test]
+    fn row_encoding_settings_are_recorded() {
+        let default = IdHashConfigBuilder::new().sketch(30).build().unwrap();
+        let settings = |config: IdHashConfig| -> Vec<(String, String)> {
+            config
+                .metadata()
+                .into_iter()
+                .filter(|(setting, _)| !default.metadata().contains_key(setting))
+                .collect()
+        };
+        let pair = |setting: &str, value: &str| vec![(setting.to_string(), value.to_string())];
+        assert_eq!(
+            settings(IdHashConfigBuilder::new().digits(5).build().unwrap()),
+            pair("digits", "5")
+        );
+        assert_eq!(
+            settings(IdHashConfigBuilder::new().characters(16).build().unwrap()),
+            pair("characters", "16")
+        );
+        assert_eq!(
+            settings(
+                IdHashConfigBuilder::new()
+                    .column_order(ColumnOrder::ByName)
+                    .build()
+                    .unwrap()
+            ),
+            pair("column_order", "by_name")
+        );
+        // Filters are recorded the same however their literals are quoted
+        let filter = |filter: &str| {
+            settings(
+                IdHashConfigBuilder::new()
+                    .filter(RowFilter::parse(filter).unwrap())
+                    .build()
+                    .unwrap(),
+            )
+        };
+        assert_eq!(
+            filter("a < 2022-01-01 and b = x"),
+            filter("a<'2022-01-01' && b == \"x\"")
+        );
+        assert_eq!(
+            filter("a < 2022-01-01"),
+            pair("filter", "\"a\" < \"2022-01-01\"")
+        );
+
+        // Sketches built with different row encodings cannot be combined
+        let sketch = |config: IdHashConfig| {
+            _read_fingerprint("data/ExampleData.csv", config)
+                .sketch
+                .unwrap()
+        };
+        let rounded = IdHashConfigBuilder::new()
+            .sketch(30)
+            .digits(3)
+            .build()
+            .unwrap();
+        assert_eq!(
+            sketch(default.clone()).subtract(&sketch(rounded)).err(),
+            Some(IbltError::SettingsMismatch)
+        );
+    }
+
+    #[test]
+    fn keyed_hash_depends_on_key() {
+        let fingerprint = |key: [u8; 32]| {
+            let config = IdHashConfigBuilder::new().key(key).build().unwrap();
+            _read_fingerprint("data/ExampleData.csv", config)
+        };
+        let first = fingerprint([1; 32]);
+        let second = fingerprint([2; 32]);
+        assert_eq!(first.metadata["hasher"], "blake3");
+        assert_ne!(first.data, second.data);
+        assert_eq!(first, fingerprint([1; 32]));
+        assert_eq!(
+            first.compare(&second).differing_settings,
+            vec![String::from("key_id")]
+        );
+        assert_eq!(
+            IdHashConfigBuilder::new()
+                .hasher(row_hasher("xxh3").unwrap())
+                .key([1; 32])
+                .build()
+                .err(),
+            Some(ConfigError::KeyNotSupported("xxh3"))
+        );
+    }
+
+    #[test]
+    fn key_files_must_be_hexadecimal() {
+        let path = std::env::temp_dir().join("idhash_multibyte_key");
+        // 64 bytes, but not 64 hexadecimal characters
+        std::fs::write(&path, "é".repeat(32)).unwrap();
+        assert!(read_key_file(path.to_str().unwrap()).is_err());
+        std::fs::write(&path, "ab".repeat(32)).unwrap();
+        assert_eq!(read_key_file(path.to_str().unwrap()).unwrap(), [0xab; 32]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lthash_supports_add_and_remove() {
+        let config = IdHashConfigBuilder::new()
+            .multiset(MultisetHash::LtHash)
+            .build()
+            .unwrap();
+        let fingerprint = |batches: &dyn Fn(CSVReader) -> Vec<Chunk<ThreadArrayChunk>>| {
+            let reader = CSVReader::new("data/ExampleData.csv".to_string(), 100, 1024);
+            let csv_schema = reader.schema.clone();
+            calculate_fingerprint(batches(reader).into_iter(), csv_schema, config.clone())
+                .unwrap()
+                .lthash
+                .unwrap()
+        };
+        let full = fingerprint(&|reader| reader.collect());
+        let head = fingerprint(&|reader| reader.take(3).collect());
+        let tail = fingerprint(&|reader| reader.skip(3).collect());
+        assert_eq!(head.clone().merge(&tail), Ok(full.clone()));
+        assert_eq!(full.clone().subtract(&head), Ok(tail));
+        assert_eq!(LtHash::from_bytes(&full.to_bytes(), None), Some(full));
+    }
+
+    #[test]
+    fn keyed_lthash_depends_on_key() {
+        let lthash = |key: Option<[u8; 32]>| {
+            let mut builder = IdHashConfigBuilder::new();
+            builder
+                .hasher(row_hasher("blake3").unwrap())
+                .multiset(MultisetHash::LtHash);
+            if let Some(key) = key {
+                builder.key(key);
+            }
+            _read_fingerprint("data/ExampleData.csv", builder.build().unwrap())
+                .lthash
+                .unwrap()
+        };
+        let unkeyed = lthash(None);
+        let first = lthash(Some([1; 32]));
+        assert_ne!(unkeyed.to_bytes(), first.to_bytes());
+        assert_ne!(first.to_bytes(), lthash(Some([2; 32])).to_bytes());
+        assert_eq!(first, lthash(Some([1; 32])));
+        assert_eq!(
+            first.clone().merge(&lthash(Some([2; 32]))).err(),
+            Some(LtHashError::KeyMismatch)
+        );
+        assert_eq!(
+            first.subtract(&unkeyed).err(),
+            Some(LtHashError::KeyMismatch)
+        );
+    }
+
+    #[test]
+    fn wide_hashes_truncate_to_narrow_hashes() {
+        let fingerprint = |width: Width| {
+            let config = IdHashConfigBuilder::new().width(width).build().unwrap();
+            let reader = CSVReader::new("data/ExampleData.csv".to_string(), 100, 1024);
+            let csv_schema = reader.schema.clone();
+            calculate_fingerprint_par(reader.par_bridge(), csv_schema, config).unwrap()
+        };
+        let narrow = fingerprint(Width::W128);
+        let medium = fingerprint(Width::W256);
+        let wide = fingerprint(Width::W512);
+        assert_eq!(medium.wide.len(), 2);
+        assert_eq!(wide.wide.len(), 4);
+        assert_eq!(medium.data, narrow.data);
+        assert_eq!(wide.wide[..2], medium.wide[..]);
+        assert!(wide.to_hex().starts_with(&narrow.to_hex()));
+    }
+
+    #[test]
+    fn unf6_matches_published_examples() {
+        let unf = |array: Box<dyn Array>| {
+            let config = IdHashConfigBuilder::new().unf6(true).build().unwrap();
+            _arrays_fingerprint(vec![("x", array)], config)
+                .unf
+                .unwrap()
+                .columns
+                .remove(0)
+                .1
+        };
+        assert_eq!(
+            unf(Box::new(Float64Array::from(&[
+                Some(1.23456789),
+                None,
+                Some(0.0)
+            ]))),
+            "UNF:6:Do5dfAoOOFt4FSj0JcByEw=="
+        );
+        assert_eq!(
+            unf(Box::new(Int64Array::from_vec((1..=20).collect()))),
+            "UNF:6:/FIOZM/29oC3TK/IE52m2A=="
+        );
+    }
+
+    #[test]
+    fn unf6_number_formatting() {
+        assert_eq!(unf6_number(1.0, 7), "+1.e+");
+        assert_eq!(unf6_number(300.0, 7), "+3.e+2");
+        assert_eq!(unf6_number(-0.003, 7), "-3.e-3");
+        assert_eq!(unf6_number(1.23456789, 7), "+1.234568e+");
+        assert_eq!(unf6_number(0.0, 7), "+0.e+");
+    }
+
+    #[test]
+    fn order_sensitive_hash_detects_sorting() {
+        let config = IdHashConfigBuilder::new()
+            .order_sensitive(true)
+            .build()
+            .unwrap();
+        let fingerprint = |file_path: &str, batch_size: usize| {
+            let reader = CSVReader::new(file_path.to_string(), 100, batch_size);
+            let csv_schema = reader.schema.clone();
+            calculate_fingerprint(reader, csv_schema, config.clone()).unwrap()
+        };
+        let original = fingerprint("data/ExampleDateData.csv", 1024);
+        let sorted = fingerprint("data/ExampleDateDataReversed.csv", 1024);
+        assert_eq!(
+            original.ordered,
+            fingerprint("data/ExampleDateData.csv", 1).ordered
+        );
+        let comparison = original.compare(&sorted);
+        assert!(comparison.data_matches);
+        assert_eq!(comparison.order_matches, Some(false));
+        assert_eq!(comparison.to_string(), "same rows, different order");
+    }
+
+    #[test]
+    fn fully_invariant_fingerprints_are_equal() {
+        let fingerprint = |file_path: &str| {
+            let config = IdHashConfigBuilder::new()
+                .fully_invariant(true)
+                .schema(true)
+                .min_max(true)
+                .unf6(true)
+                .build()
+                .unwrap();
+            _read_fingerprint(file_path, config)
+        };
+        let original = fingerprint("data/ExampleData.csv");
+        assert_eq!(original.columns.len(), 2);
+        assert_eq!(original, fingerprint("data/ExampleDataReordered.csv"));
+        let sorted = fingerprint("data/ExampleDataSorted.csv");
+        assert_eq!(original.combined(), sorted.combined());
+        assert_eq!(original.columns, sorted.columns);
+
+        let config = IdHashConfigBuilder::new()
+            .column_order(ColumnOrder::ByName)
+            .fully_invariant(false)
+            .build()
+            .unwrap();
+        assert_eq!(config.column_order, ColumnOrder::ByName);
+    }
+
+    #[test]
+    fn column_selection_matches_projected_reader() {
+        let selection = ColumnSelection {
+            include: vec![ColumnSelector::parse("[ab]").unwrap()],
+            exclude: vec![ColumnSelector::parse("0").unwrap()],
+        };
+        let config = IdHashConfigBuilder::new()
+            .schema(true)
+            .column_fingerprints(true)
+            .selection(selection.clone())
+            .build()
+            .unwrap();
+        let selected = _read_fingerprint("data/ExampleDateData.csv", config);
+        assert_eq!(selected.columns.len(), 1);
+        assert_eq!(selected.columns[0].name, "a");
+
+        let mut reader = CSVReader::new("data/ExampleDateData.csv".to_string(), 100, 1024);
+        reader.project(&selection);
+        let csv_schema = reader.schema.clone();
+        let config = IdHashConfigBuilder::new()
+            .schema(true)
+            .column_fingerprints(true)
+            .build()
+            .unwrap();
+        // The same rows are hashed, though the selection is recorded as a setting
+        let comparison =
+            selected.compare(&calculate_fingerprint(reader, csv_schema, config).unwrap());
+        assert!(comparison.is_match());
+        assert!(comparison.differing_columns.is_empty());
+        assert_eq!(comparison.differing_settings, vec!["exclude", "include"]);
+
+        let reader = CSVReader::new("data/ExampleData.csv".to_string(), 100, 1024);
+        let csv_schema = reader.schema.clone();
+        let config = IdHashConfigBuilder::new()
+            .selection(ColumnSelection {
+                include: vec![ColumnSelector::parse("typo*").unwrap()],
+                exclude: vec![],
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            calculate_fingerprint(reader, csv_schema, config).err(),
+            Some(FingerprintError::NoColumns)
+        );
+    }
+
     #[test]
     fn load_date_data_from_file() {
         let file_path = "data/ExampleDateData.csv";
         _read_return_hash(file_path, 1024);
     }
+
+    #[test]
+    fn column_mapping_aligns_vendor_headers() {
+        let fingerprint = |file_path: &str, mapping: ColumnMapping| {
+            let config = IdHashConfigBuilder::new()
+                .schema(true)
+                .column_fingerprints(true)
+                .mapping(mapping)
+                .build()
+                .unwrap();
+            _read_fingerprint(file_path, config)
+        };
+        let vendor = fingerprint("data/ExampleDataVendor.csv", ColumnMapping::default());
+        let unordered = fingerprint(
+            "data/ExampleData.csv",
+            ColumnMapping::from_file("data/ExampleDataMapping.csv", false).unwrap(),
+        );
+        assert_eq!(unordered.compare(&vendor).schema_matches, Some(false));
+        let mapped = fingerprint(
+            "data/ExampleData.csv",
+            ColumnMapping::from_file("data/ExampleDataMapping.csv", true).unwrap(),
+        );
+        let comparison = mapped.compare(&vendor);
+        assert!(comparison.is_match());
+        assert!(comparison.differing_columns.is_empty());
+        assert_eq!(comparison.differing_settings, vec!["mapping", "reorder"]);
+    }
+
+    #[test]
+    fn row_filter_skips_rows_before_hashing() {
+        let fingerprint = |file_path: &str, filter: &str| {
+            let config = IdHashConfigBuilder::new()
+                .filter(RowFilter::parse(filter).unwrap())
+                .build()
+                .unwrap();
+            _read_fingerprint(file_path, config)
+        };
+        let original = fingerprint("data/ExampleDateData.csv", "a < 2022-01-01");
+        let modified = fingerprint("data/ExampleDateDataModified.csv", "a < '2022-01-01'");
+        assert_eq!(original.summary.rows, 1);
+        assert_eq!(original, modified);
+
+        let original = fingerprint(
+            "data/ExampleDateData.csv",
+            "a >= 2022-01-01 && a != 2022-03-05",
+        );
+        let modified = fingerprint("data/ExampleDateDataModified.csv", "a >= 2022-01-01");
+        assert_eq!(original.summary.rows, 1);
+        assert!(!original.compare(&modified).is_match());
+
+        // Separators inside quoted literals are part of the literal
+        let filter = RowFilter::parse("name == 'Tom and Jerry' && 'a&&b' != \"x and y\"").unwrap();
+        let terms: Vec<(&str, &str)> = filter
+            .predicates
+            .iter()
+            .map(|predicate| (predicate.column.as_str(), predicate.literal.as_str()))
+            .collect();
+        assert_eq!(terms, vec![("name", "Tom and Jerry"), ("a&&b", "x and y")]);
+        assert_eq!(
+            RowFilter::parse("band == 1 and brand == 2")
+                .unwrap()
+                .predicates
+                .len(),
+            2
+        );
+
+        let reader = CSVReader::new("data/ExampleDateData.csv".to_string(), 100, 1024);
+        let csv_schema = reader.schema.clone();
+        let config = IdHashConfigBuilder::new()
+            .filter(RowFilter::parse("missing == 1").unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(
+            calculate_fingerprint(reader, csv_schema, config).err(),
+            Some(FingerprintError::Filter(FilterError::UnknownColumn(
+                String::from("missing")
+            )))
+        );
+    }
+
+    #[test]
+    fn column_options_override_digits() {
+        let fingerprint = |a: [f64; 2], options: ColumnOptions| {
+            let config = IdHashConfigBuilder::new()
+                .version(Version::V2)
+                .column_fingerprints(true)
+                .column_options("a", options)
+                .build()
+                .unwrap();
+            _arrays_fingerprint(
+                vec![
+                    ("a", Box::new(Float64Array::from_slice(a))),
+                    ("b", Box::new(Float64Array::from_slice([0.5, 0.25]))),
+                ],
+                config,
+            )
+        };
+        let digits = |digits: usize| ColumnOptions {
+            digits: Some(digits),
+            ..ColumnOptions::default()
+        };
+        // The values of `a` differ in the 6th significant digit
+        let left = [1.23451, 2.5];
+        let right = [1.23459, 2.5];
+        let (low, high) = (fingerprint(left, digits(4)), fingerprint(right, digits(4)));
+        assert_eq!(low.columns[0].hash, high.columns[0].hash);
+        assert_eq!(low.data, high.data);
+        let (low, high) = (fingerprint(left, digits(6)), fingerprint(right, digits(6)));
+        assert_ne!(low.columns[0].hash, high.columns[0].hash);
+        assert_eq!(low.columns[1].hash, high.columns[1].hash);
+
+        let default = fingerprint(left, ColumnOptions::default());
+        let overridden = fingerprint(left, digits(4));
+        assert_eq!(
+            overridden
+                .metadata
+                .get("column.a.digits")
+                .map(String::as_str),
+            Some("4")
+        );
+        assert_eq!(default.compare(&overridden).differing_settings.len(), 1);
+
+        for version in [Version::V1, Version::V2] {
+            assert_eq!(
+                IdHashConfigBuilder::new()
+                    .version(version)
+                    .digits(0)
+                    .build()
+                    .err(),
+                Some(ConfigError::ZeroDigits)
+            );
+            assert_eq!(
+                IdHashConfigBuilder::new()
+                    .version(version)
+                    .column_options("a", digits(0))
+                    .build()
+                    .err(),
+                Some(ConfigError::ZeroDigits)
+            );
+        }
+    }
+
+    #[test]
+    fn resolutions_report_matching_digits() {
+        let fingerprint = |values: [f64; 2]| {
+            let config = IdHashConfigBuilder::new()
+                .column_fingerprints(true)
+                .resolutions(vec![7, 3, 5])
+                .build()
+                .unwrap();
+            _arrays_fingerprint(
+                vec![("x", Box::new(Float64Array::from_slice(values)))],
+                config,
+            )
+        };
+        let left = fingerprint([1.234567, 98765.43]);
+        let right = fingerprint([1.234612, 98775.21]);
+        assert_eq!(
+            left.resolutions
+                .iter()
+                .map(|resolution| resolution.digits)
+                .collect::<Vec<usize>>(),
+            vec![3, 5, 7]
+        );
+        assert_eq!(left.resolutions[2].data, left.data);
+        assert_eq!(left.resolutions[0].columns.len(), 1);
+        let comparison = left.compare(&right);
+        assert!(!comparison.data_matches);
+        assert_eq!(comparison.matching_resolutions, vec![3]);
+        assert_eq!(comparison.differing_resolutions, vec![5, 7]);
+        assert!(comparison
+            .to_string()
+            .contains("equal to 3 significant digits, not 5"));
+
+        assert_eq!(
+            IdHashConfigBuilder::new()
+                .resolutions(vec![0, 3])
+                .build()
+                .err(),
+            Some(ConfigError::ZeroDigits)
+        );
+        assert_eq!(
+            IdHashConfigBuilder::new()
+                .version(Version::V1)
+                .resolutions(vec![3])
+                .build()
+                .err(),
+            Some(ConfigError::RoundingNeedsV2)
+        );
+        let config = IdHashConfigBuilder::new().build().unwrap();
+        assert_eq!(config.with_digits(0).err(), Some(ConfigError::ZeroDigits));
+    }
+
+    #[test]
+    fn tolerance_search_finds_matching_digits() {
+        let fingerprint = |config: IdHashConfig, values: [f64; 2], labels: [&str; 2]| {
+            _arrays_fingerprint(
+                vec![
+                    ("x", Box::new(Float64Array::from_slice(values))),
+                    ("y", Box::new(Float64Array::from_slice([0.5, 0.25]))),
+                    ("label", Box::new(Utf8Array::<i32>::from_slice(labels))),
+                ],
+                config,
+            )
+        };
+        let mut passes = 0;
+        let tolerance =
+            find_tolerance(&IdHashConfigBuilder::new().build().unwrap(), 15, |config| {
+                passes += 1;
+                (
+                    fingerprint(config.clone(), [1.23456789, 98765.4321], ["a", "b"]),
+                    fingerprint(config, [1.23457012, 98765.4299], ["a", "c"]),
+                )
+            })
+            .unwrap();
+        let digits: Vec<Option<usize>> = tolerance
+            .columns
+            .iter()
+            .map(|column| column.digits)
+            .collect();
+        assert_eq!(digits, vec![Some(6), Some(15), None]);
+        assert_eq!(tolerance.digits(), None);
+        assert!(passes <= 5);
+
+        let unreachable = |_| -> (Fingerprint, Fingerprint) { unreachable!() };
+        let config = IdHashConfigBuilder::new().build().unwrap();
+        assert_eq!(
+            find_tolerance(&config, 0, unreachable).err(),
+            Some(ConfigError::ZeroDigits)
+        );
+        let config = IdHashConfigBuilder::new()
+            .version(Version::V1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            find_tolerance(&config, 15, unreachable).err(),
+            Some(ConfigError::RoundingNeedsV2)
+        );
+    }
+
+    #[test]
+    fn sketches_recover_differing_rows() {
+        let batch = |values: &[i64]| {
+            Chunk::new(vec![ThreadArrayChunk::from(Arc::new(
+                Box::new(Int64Array::from_slice(values)) as Box<dyn Array>,
+            ))])
+        };
+        let schema = Arc::new(Schema::from(vec![Field::new("x", DataType::Int64, true)]));
+        let config = IdHashConfigBuilder::new().sketch(30).build().unwrap();
+        let left: Vec<i64> = (0..1000).collect();
+        let right: Vec<i64> = (3..1000).chain([-1, -2]).collect();
+        let left_sketch =
+            calculate_fingerprint(left.chunks(300).map(batch), schema.clone(), config.clone())
+                .unwrap()
+                .sketch
+                .unwrap();
+        let right_sketch = calculate_fingerprint(
+            std::iter::once(batch(&right)),
+            schema.clone(),
+            config.clone(),
+        )
+        .unwrap()
+        .sketch
+        .unwrap();
+        let right_sketch = Iblt::from_bytes(&right_sketch.to_bytes()).unwrap();
+        let difference = left_sketch
+            .clone()
+            .subtract(&right_sketch)
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(difference.left_only.len(), 3);
+        assert_eq!(difference.right_only.len(), 2);
+        let hashes = difference.left_only.iter().copied().collect();
+        let mut rows: Vec<String> = find_rows(
+            std::iter::once(batch(&left)),
+            schema.clone(),
+            config,
+            &hashes,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(_, values)| values)
+        .collect();
+        rows.sort();
+        assert_eq!(rows, vec!["0", "1", "2"]);
+
+        let config = IdHashConfigBuilder::new()
+            .sketch(30)
+            .hasher(row_hasher("xxh3").unwrap())
+            .build()
+            .unwrap();
+        let other_sketch =
+            calculate_fingerprint(std::iter::once(batch(&right)), schema.clone(), config)
+                .unwrap()
+                .sketch
+                .unwrap();
+        assert_eq!(
+            left_sketch.clone().subtract(&other_sketch).err(),
+            Some(IbltError::SettingsMismatch)
+        );
+        assert_eq!(
+            left_sketch.clone().merge(&other_sketch).err(),
+            Some(IbltError::SettingsMismatch)
+        );
+        assert_eq!(
+            Iblt::new(30).merge(&Iblt::new(60)).err(),
+            Some(IbltError::SizeMismatch(30, 60))
+        );
+
+        let config = IdHashConfigBuilder::new().sketch(30).build().unwrap();
+        let empty = calculate_fingerprint(std::iter::empty(), schema, config)
+            .unwrap()
+            .sketch
+            .unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(left_sketch.clone().subtract(&empty), Ok(left_sketch));
+
+        // Rows repeated more often on one side are listed once per extra
+        // occurrence, including even counts which lose high bits when divided
+        let (high, odd) = (u128::MAX - 12345, 1u128 << 100 | 77);
+        let (mut left, mut right) = (Iblt::new(30), Iblt::new(30));
+        for key in [high, high, high, high, odd, odd, odd, 5] {
+            left.insert(key);
+        }
+        for key in [odd, 5, 6, 6] {
+            right.insert(key);
+        }
+        let difference = left.subtract(&right).unwrap().decode().unwrap();
+        assert_eq!(difference.left_only, vec![odd, odd, high, high, high, high]);
+        assert_eq!(difference.right_only, vec![6, 6]);
+    }
+
+    #[test]
+    fn min_hash_estimates_similarity() {
+        let min_hash = |values: Vec<i64>, version: Version| {
+            let schema = Arc::new(Schema::from(vec![Field::new("x", DataType::Int64, true)]));
+            let batches: Vec<Chunk<ThreadArrayChunk>> = values
+                .chunks(700)
+                .map(|values| {
+                    Chunk::new(vec![ThreadArrayChunk::from(Arc::new(Box::new(
+                        Int64Array::from_slice(values),
+                    )
+                        as Box<dyn Array>))])
+                })
+                .collect();
+            let config = IdHashConfigBuilder::new()
+                .version(version)
+                .min_hash(256)
+                .build()
+                .unwrap();
+            let min_hash = calculate_fingerprint(batches.into_iter(), schema, config)
+                .unwrap()
+                .min_hash
+                .unwrap();
+            MinHash::from_bytes(&min_hash.to_bytes()).unwrap()
+        };
+        let similarity = min_hash((0..2000).collect(), Version::V2)
+            .similarity(&min_hash((500..2500).collect(), Version::V2))
+            .unwrap();
+        assert!((similarity.jaccard - 0.6).abs() < 0.1);
+        assert!((similarity.shared_rows - 1500.0).abs() < 300.0);
+
+        // Small datasets are sampled entirely, so the estimate is exact
+        let similarity = min_hash(vec![1, 2, 3, 3], Version::V2)
+            .similarity(&min_hash(vec![2, 3, 4], Version::V2))
+            .unwrap();
+        assert_eq!(similarity.jaccard, 0.5);
+        assert_eq!(similarity.shared_rows, 2.0);
+
+        assert_eq!(
+            min_hash(vec![1, 2, 3], Version::V2)
+                .similarity(&min_hash(vec![1, 2, 3], Version::V1))
+                .err(),
+            Some(MinHashError::SettingsMismatch)
+        );
+
+        // A dataset without rows shares none with another
+        let empty = min_hash(vec![], Version::V2);
+        assert_eq!(empty.distinct_rows(), 0.0);
+        assert_eq!(
+            empty
+                .similarity(&min_hash(vec![1, 2], Version::V2))
+                .unwrap()
+                .jaccard,
+            0.0
+        );
+    }
+
+    #[test]
+    fn hyperloglog_counts_distinct_rows() {
+        let schema = Arc::new(Schema::from(vec![Field::new("x", DataType::Int64, true)]));
+        let values: Vec<i64> = (0..20000).map(|x| x % 5000).collect();
+        let batches = || {
+            values.chunks(3000).map(|values| {
+                Chunk::new(vec![ThreadArrayChunk::from(Arc::new(
+                    Box::new(Int64Array::from_slice(values)) as Box<dyn Array>,
+                ))])
+            })
+        };
+        let config = IdHashConfigBuilder::new().hyperloglog(12).build().unwrap();
+        let sequential = calculate_fingerprint(batches(), schema.clone(), config.clone()).unwrap();
+        let parallel = calculate_fingerprint_par(
+            batches().collect::<Vec<_>>().into_par_iter(),
+            schema,
+            config.clone(),
+        )
+        .unwrap();
+        assert_eq!(sequential.summary.rows, 20000);
+        assert_eq!(sequential.hyperloglog, parallel.hyperloglog);
+        let distinct_rows = sequential.distinct_rows().unwrap();
+        assert!((distinct_rows - 5000.0).abs() < 250.0);
+        let sketch = sequential.hyperloglog.unwrap();
+        assert_eq!(sketch.settings(), config.settings_id());
+        assert_eq!(HyperLogLog::from_bytes(&sketch.to_bytes()), Some(sketch));
+
+        let config = IdHashConfigBuilder::new().hyperloglog(12).build().unwrap();
+        let schema = Arc::new(Schema::from(vec![Field::new("x", DataType::Int64, true)]));
+        let empty = calculate_fingerprint(std::iter::empty(), schema, config).unwrap();
+        assert_eq!(empty.distinct_rows(), Some(0.0));
+
+        for precision in [3, 19] {
+            assert_eq!(
+                IdHashConfigBuilder::new()
+                    .hyperloglog(precision)
+                    .build()
+                    .err(),
+                Some(ConfigError::InvalidPrecision(precision))
+            );
+        }
+        assert_eq!(
+            HyperLogLog::new(10).merge(&HyperLogLog::new(12)).err(),
+            Some(HyperLogLogError::PrecisionMismatch(10, 12))
+        );
+        assert_eq!(
+            HyperLogLog::new(12)
+                .with_settings(1)
+                .merge(&HyperLogLog::new(12))
+                .err(),
+            Some(HyperLogLogError::SettingsMismatch)
+        );
+    }
+
+    #[test]
+    fn numeric_invariant_ignores_numeric_types() {
+        let fingerprint = |file_path: &str, numeric_invariant: bool| {
+            let config = IdHashConfigBuilder::new()
+                .schema(true)
+                .numeric_invariant(numeric_invariant)
+                .build()
+                .unwrap();
+            _read_fingerprint(file_path, config)
+        };
+        assert_ne!(
+            fingerprint("data/ExampleIntegers.csv", false).combined(),
+            fingerprint("data/ExampleIntegersAsFloats.csv", false).combined()
+        );
+        assert_eq!(
+            fingerprint("data/ExampleIntegers.csv", true),
+            fingerprint("data/ExampleIntegersAsFloats.csv", true)
+        );
+
+        let fingerprint = |array: Box<dyn Array>| {
+            let config = IdHashConfigBuilder::new()
+                .numeric_invariant(true)
+                .build()
+                .unwrap();
+            _arrays_fingerprint(vec![("x", array)], config).data
+        };
+        assert_eq!(
+            fingerprint(Box::new(
+                Int128Array::from(&[Some(1050), None]).to(DataType::Decimal(10, 2))
+            )),
+            fingerprint(Box::new(Float64Array::from(&[Some(10.5), None])))
+        );
+    }
+
+    #[test]
+    fn schema_inference_modes_are_row_order_invariant() {
+        let fingerprint = |file_path: &str, inference: SchemaInference| {
+            let reader = CSVReader::with_inference(file_path.to_string(), inference, 1024);
+            let csv_schema = reader.schema.clone();
+            let config = IdHashConfigBuilder::new().schema(true).build().unwrap();
+            calculate_fingerprint(reader, csv_schema, config).unwrap()
+        };
+        assert_ne!(
+            fingerprint("data/ExampleMixedTypes.csv", SchemaInference::Rows(1)),
+            fingerprint(
+                "data/ExampleMixedTypesShuffled.csv",
+                SchemaInference::Rows(1)
+            )
+        );
+        for inference in [SchemaInference::Full, SchemaInference::Text] {
+            assert_eq!(
+                fingerprint("data/ExampleMixedTypes.csv", inference),
+                fingerprint("data/ExampleMixedTypesShuffled.csv", inference)
+            );
+        }
+    }
+
+    #[test]
+    fn null_tokens_are_hashed_as_nulls() {
+        let fingerprint = |file_path: &str, null_tokens: Vec<String>| {
+            let reader =
+                CSVReader::with_inference(file_path.to_string(), SchemaInference::Text, 1024);
+            let csv_schema = reader.schema.clone();
+            let config = IdHashConfigBuilder::new()
+                .null_tokens(null_tokens)
+                .build()
+                .unwrap();
+            calculate_fingerprint(reader, csv_schema, config).unwrap()
+        };
+        assert_ne!(
+            fingerprint("data/ExampleNullTokens.csv", vec![]),
+            fingerprint("data/ExampleNullTokensEmpty.csv", vec![])
+        );
+        let with_tokens = fingerprint("data/ExampleNullTokens.csv", common_null_tokens());
+        assert_eq!(
+            with_tokens,
+            fingerprint("data/ExampleNullTokensEmpty.csv", common_null_tokens())
+        );
+        assert_eq!(with_tokens.summary.columns[0].null_count, 1);
+
+        // A null never collides with the string "null"
+        let fingerprint = |array: Utf8Array<i32>| {
+            let config = IdHashConfigBuilder::new().build().unwrap();
+            _arrays_fingerprint(vec![("x", Box::new(array))], config).data
+        };
+        assert_ne!(
+            fingerprint(Utf8Array::from([Some("null")])),
+            fingerprint(Utf8Array::from([None::<&str>]))
+        );
+    }
+
+    #[test]
+    fn string_normalizers_ignore_export_differences() {
+        let fingerprint = |file_path: &str, status: Option<Vec<StringNormalizer>>| {
+            let config = IdHashConfigBuilder::new()
+                .string_normalizers(vec![
+                    StringNormalizer::CaseFold,
+                    StringNormalizer::Trim,
+                    StringNormalizer::CollapseWhitespace,
+                    StringNormalizer::LineEndings,
+                ])
+                .column_options(
+                    "status",
+                    ColumnOptions {
+                        string_normalizers: status,
+                        ..ColumnOptions::default()
+                    },
+                )
+                .build()
+                .unwrap();
+            _read_fingerprint(file_path, config)
+        };
+        let exported = fingerprint("data/ExampleStrings.csv", None);
+        assert_eq!(
+            exported,
+            fingerprint("data/ExampleStringsNormalized.csv", None)
+        );
+        assert_eq!(
+            exported
+                .metadata
+                .get("string_normalizers")
+                .map(String::as_str),
+            Some("line_endings,trim,collapse_whitespace,case_fold")
+        );
+        assert_ne!(
+            fingerprint("data/ExampleStrings.csv", Some(vec![])).data,
+            fingerprint("data/ExampleStringsNormalized.csv", Some(vec![])).data
+        );
+
+        // Windows line endings, and a byte order mark before the first value
+        let windows = "data/ExampleStringsCrlfBom.csv";
+        assert_ne!(exported.data, fingerprint(windows, None).data);
+        assert_eq!(
+            exported.data,
+            fingerprint(
+                windows,
+                Some(vec![StringNormalizer::StripBom, StringNormalizer::CaseFold])
+            )
+            .data
+        );
+    }
+
+    #[test]
+    fn version_2_rows_serialize_injectively() {
+        let fingerprint = |columns: Vec<(&str, Box<dyn Array>)>, version: Version| {
+            let config = IdHashConfigBuilder::new().version(version).build().unwrap();
+            _arrays_fingerprint(columns, config).data
+        };
+        let joined = || -> Vec<(&str, Box<dyn Array>)> {
+            vec![("x", Box::new(Utf8Array::<i32>::from_slice(["a\n\0b"])))]
+        };
+        let split = || -> Vec<(&str, Box<dyn Array>)> {
+            vec![
+                ("x", Box::new(Utf8Array::<i32>::from_slice(["a"]))),
+                ("y", Box::new(Utf8Array::<i32>::from_slice(["b"]))),
+            ]
+        };
+        assert_eq!(
+            fingerprint(joined(), Version::V1),
+            fingerprint(split(), Version::V1)
+        );
+        assert_ne!(
+            fingerprint(joined(), Version::V2),
+            fingerprint(split(), Version::V2)
+        );
+
+        // Floats are rounded to 7 significant digits
+        let float = |x: f64| -> Vec<(&str, Box<dyn Array>)> {
+            vec![("x", Box::new(Float64Array::from_slice([x])))]
+        };
+        assert_eq!(
+            fingerprint(float(1.23456789), Version::V2),
+            fingerprint(float(1.234568), Version::V2)
+        );
+        assert_ne!(
+            fingerprint(float(1.23456789), Version::V2),
+            fingerprint(float(1.3), Version::V2)
+        );
+    }
+
+    #[test]
+    fn version_1_keeps_existing_hashes() {
+        // Hash of a file with missing numbers before version 2 was added,
+        // inferring the types from the first row so the empty fields are nulls
+        let file_path = "data/ExampleNulls.csv";
+        let reader = CSVReader::new(file_path.to_string(), 1, 1024);
+        let csv_schema = reader.schema.clone();
+        let config = IdHashConfigBuilder::new()
+            .version(Version::V1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            calculate_idhash(reader, csv_schema, config).unwrap(),
+            195662400689087242951120696921086005835
+        );
+    }
+
+    #[test]
+    fn unsupported_types_are_errors() {
+        let schema = Arc::new(Schema::from(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("blob", DataType::Binary, true),
+        ]));
+        for version in [Version::V1, Version::V2] {
+            let chunk = Chunk::new(vec![
+                ThreadArrayChunk::from(Arc::new(
+                    Box::new(Int64Array::from_slice([1, 2])) as Box<dyn Array>
+                )),
+                ThreadArrayChunk::from(Arc::new(Box::new(BinaryArray::<i32>::from_slice([
+                    b"a", b"b",
+                ])) as Box<dyn Array>)),
+            ]);
+            let config = IdHashConfigBuilder::new().version(version).build().unwrap();
+            assert_eq!(
+                calculate_fingerprint(std::iter::once(chunk), schema.clone(), config).err(),
+                Some(FingerprintError::UnsupportedType {
+                    column: "blob".to_string(),
+                    data_type: DataType::Binary,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn small_integer_types_are_hashed() {
+        for version in [Version::V1, Version::V2] {
+            let fingerprint = |array: Box<dyn Array>| {
+                let config = IdHashConfigBuilder::new().version(version).build().unwrap();
+                _arrays_fingerprint(vec![("x", array)], config).data
+            };
+            let expected = fingerprint(Box::new(Int64Array::from(&[Some(1), Some(2), None])));
+            let arrays: Vec<Box<dyn Array>> = vec![
+                Box::new(Int8Array::from(&[Some(1), Some(2), None])),
+                Box::new(Int16Array::from(&[Some(1), Some(2), None])),
+                Box::new(UInt8Array::from(&[Some(1), Some(2), None])),
+                Box::new(UInt16Array::from(&[Some(1), Some(2), None])),
+            ];
+            for array in arrays {
+                assert_eq!(fingerprint(array), expected);
+            }
+        }
+
+        // arrow2 cannot hold half-precision floats
+        let schema = Arc::new(Schema::from(vec![Field::new("x", DataType::Float16, true)]));
+        let config = IdHashConfigBuilder::new().build().unwrap();
+        assert_eq!(
+            calculate_fingerprint(std::iter::empty(), schema, config).err(),
+            Some(FingerprintError::UnsupportedType {
+                column: "x".to_string(),
+                data_type: DataType::Float16,
+            })
+        );
+    }
+
+    #[test]
+    fn small_integer_types_in_every_mode() {
+        let modes: Vec<(&str, IdHashConfig)> = vec![
+            (
+                "numeric invariant v1",
+                IdHashConfigBuilder::new()
+                    .version(Version::V1)
+                    .numeric_invariant(true)
+                    .build()
+                    .unwrap(),
+            ),
+            (
+                "numeric invariant v2",
+                IdHashConfigBuilder::new()
+                    .numeric_invariant(true)
+                    .build()
+                    .unwrap(),
+            ),
+            (
+                "unf6",
+                IdHashConfigBuilder::new().unf6(true).build().unwrap(),
+            ),
+            (
+                "null tokens",
+                IdHashConfigBuilder::new()
+                    .null_tokens(common_null_tokens())
+                    .build()
+                    .unwrap(),
+            ),
+        ];
+        for (mode, config) in modes {
+            let fingerprint = |array: Box<dyn Array>| {
+                let fingerprint = _arrays_fingerprint(vec![("x", array)], config.clone());
+                (fingerprint.data, fingerprint.unf)
+            };
+            let expected = fingerprint(Box::new(Int64Array::from(&[Some(-1), Some(2), None])));
+            let arrays: Vec<Box<dyn Array>> = vec![
+                Box::new(Int8Array::from(&[Some(-1), Some(2), None])),
+                Box::new(Int16Array::from(&[Some(-1), Some(2), None])),
+            ];
+            for array in arrays {
+                assert_eq!(fingerprint(array), expected, "{}", mode);
+            }
+            let expected = fingerprint(Box::new(Int64Array::from(&[Some(1), Some(2), None])));
+            let actual = fingerprint(Box::new(UInt8Array::from(&[Some(1), Some(2), None])));
+            assert_eq!(actual, expected, "{}", mode);
+        }
+    }
 }
@@ -1,12 +1,85 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::process;
 use std::time::Instant;
 
 use clap::{App, Arg};
 
-use idhash::config::IdHashConfigBuilder;
-use idhash::utils::CSVReader;
-use idhash::{calculate_idhash, calculate_idhash_par};
+use idhash::config::{
+    ColumnOptions, ColumnOrder, IdHashConfig, IdHashConfigBuilder, MultisetHash, Version, Width,
+};
+use idhash::filter::RowFilter;
+use idhash::iblt::Iblt;
+use idhash::mapping::ColumnMapping;
+use idhash::minhash::MinHash;
+use idhash::normalize::StringNormalizer;
+use idhash::nulls::common_null_tokens;
+use idhash::row_hasher::row_hasher;
+use idhash::selection::{ColumnSelection, ColumnSelector};
+use idhash::tolerance::find_tolerance;
+use idhash::utils::{read_key_file, CSVReader, SchemaInference};
+use idhash::{calculate_fingerprint, calculate_fingerprint_par, find_rows, Fingerprint};
 use rayon::iter::ParallelBridge;
 
+/// How an input file is read, independently of how it is hashed
+struct ReadOptions {
+    inference: SchemaInference,
+    batch_size: usize,
+    n_cpus: usize,
+    /// Pushed down to the reader, so excluded columns are never decoded
+    selection: ColumnSelection,
+}
+
+/// Report invalid settings or input and exit, without a panic backtrace
+fn exit_with_error(context: &str, err: impl fmt::Display) -> ! {
+    eprintln!("{}: {}", context, err);
+    process::exit(2)
+}
+
+/// Comma-separated normalizer names, e.g. `trim,case_fold`
+fn parse_normalizers(names: &str) -> Vec<StringNormalizer> {
+    names
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            StringNormalizer::parse(name.trim())
+                .unwrap_or_else(|| panic!("Unknown string normalizer `{}`", name))
+        })
+        .collect()
+}
+
+/// Open a file for hashing, pushing the column selection down to the reader
+/// where possible
+fn open_file(file_path: &str, options: &ReadOptions, config: &mut IdHashConfig) -> CSVReader {
+    let mut csv =
+        CSVReader::with_inference(file_path.to_string(), options.inference, options.batch_size);
+    if !config.filter.is_all() {
+        // The filter may refer to columns which are not hashed, so every column is read
+        config.selection = options.selection.clone();
+    } else if !options.selection.is_all() {
+        csv.project(&options.selection);
+    }
+    csv
+}
+
+fn fingerprint_file(
+    file_path: &str,
+    options: &ReadOptions,
+    mut config: IdHashConfig,
+) -> Fingerprint {
+    let csv = open_file(file_path, options, &mut config);
+    let csv_schema = csv.schema.clone();
+    // UNF values and the ordered hash depend on row order, so can only be
+    // computed sequentially
+    if options.n_cpus > 1 && !config.unf6 && !config.order_sensitive {
+        calculate_fingerprint_par(csv.par_bridge(), csv_schema, config)
+    } else {
+        calculate_fingerprint(csv, csv_schema, config)
+    }
+    .unwrap_or_else(|err| exit_with_error(file_path, err))
+}
+
 fn main() {
     let matches = App::new("IdHash")
         .version("0.0.3")
@@ -42,7 +115,14 @@ fn main() {
                 .short("r")
                 .value_name("INFERENCE_ROWS")
                 .default_value("100")
-                .takes_value(true),
+                .takes_value(true)
+                .help("Rows to infer column types from, or `all` to read the whole file first"),
+        )
+        .arg(
+            Arg::with_name("text")
+                .long("text")
+                .conflicts_with("inference_rows")
+                .help("Read every column as text instead of inferring types"),
         )
         .arg(
             Arg::with_name("batch_size")
@@ -58,32 +138,561 @@ fn main() {
                 .default_value("1")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("schema")
+                .short("s")
+                .long("schema")
+                .help("Include column names, types and nullability in the hash"),
+        )
+        .arg(
+            Arg::with_name("column_order")
+                .short("o")
+                .long("column-order")
+                .value_name("ORDER")
+                .possible_values(&["positional", "name"])
+                .default_value("positional")
+                .takes_value(true)
+                .help("Combine columns by position, or by name so that column order is ignored"),
+        )
+        .arg(
+            Arg::with_name("column_fingerprints")
+                .long("column-hashes")
+                .help("Also report an order-invariant hash for every column"),
+        )
+        .arg(
+            Arg::with_name("summary").long("summary").help(
+                "Report the row count and the null count, minimum and maximum of every column",
+            ),
+        )
+        .arg(
+            Arg::with_name("hasher")
+                .long("hasher")
+                .value_name("HASHER")
+                .possible_values(&["murmur3", "xxh3", "siphash", "blake3"])
+                .takes_value(true)
+                .help("Hash function applied to each row [default: murmur3, or blake3 with a key]"),
+        )
+        .arg(
+            Arg::with_name("key_file")
+                .long("key-file")
+                .value_name("KEY_FILE")
+                .takes_value(true)
+                .help("Secret key for the row hash, as 32 raw bytes or 64 hexadecimal characters"),
+        )
+        .arg(
+            Arg::with_name("multiset")
+                .long("multiset")
+                .value_name("MULTISET")
+                .possible_values(&["sum", "lthash"])
+                .default_value("sum")
+                .takes_value(true)
+                .help("Also produce a secure LtHash of the rows, which cannot be forged"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .value_name("BITS")
+                .possible_values(&["128", "256", "512"])
+                .default_value("128")
+                .takes_value(true)
+                .help("Size of the data hash, built from independent row hash lanes"),
+        )
+        .arg(
+            Arg::with_name("version")
+                .long("algorithm-version")
+                .value_name("VERSION")
+                .possible_values(&["1", "2"])
+                .default_value("2")
+                .takes_value(true)
+                .help("Row encoding; 1 reproduces hashes from before version 2 was introduced"),
+        )
+        .arg(
+            Arg::with_name("unf")
+                .long("unf")
+                .help("Also report UNF version 6 values for the dataset and every column"),
+        )
+        .arg(
+            Arg::with_name("ordered")
+                .long("ordered")
+                .help("Also report a hash that depends on row order"),
+        )
+        .arg(
+            Arg::with_name("invariant")
+                .long("invariant")
+                .help("Ignore both row and column order, matching columns by name"),
+        )
+        .arg(
+            Arg::with_name("numeric_invariant")
+                .long("numeric-invariant")
+                .help("Hash equal numbers equally whether read as integers, floats or decimals"),
+        )
+        .arg(
+            Arg::with_name("null_token")
+                .long("null-token")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Treat this value as null, e.g. NA or an empty string; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("common_nulls")
+                .long("common-nulls")
+                .help("Treat common null tokens as null: empty, NA, N/A, NaN, None, null and NULL"),
+        )
+        .arg(
+            Arg::with_name("column_digits")
+                .long("column-digits")
+                .value_name("COLUMN=DIGITS")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Significant digits for one column, overriding --digits; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("column_characters")
+                .long("column-characters")
+                .value_name("COLUMN=CHARACTERS")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Characters for one column, overriding --characters; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("normalize")
+                .long("normalize")
+                .value_name("NORMALIZERS")
+                .takes_value(true)
+                .help(
+                    "Normalize text before hashing, a comma-separated list of strip_bom, \
+                     line_endings, trim, collapse_whitespace and case_fold",
+                ),
+        )
+        .arg(
+            Arg::with_name("column_normalize")
+                .long("column-normalize")
+                .value_name("COLUMN=NORMALIZERS")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Text normalizers for one column, overriding --normalize; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("EXPRESSION")
+                .takes_value(true)
+                .help("Only hash rows matching e.g. \"region == 'EU' and date >= 2024-01-01\""),
+        )
+        .arg(
+            Arg::with_name("mapping")
+                .long("mapping")
+                .value_name("MAPPING_FILE")
+                .takes_value(true)
+                .help("File of `source,target` lines renaming columns before hashing"),
+        )
+        .arg(
+            Arg::with_name("reorder")
+                .long("reorder")
+                .requires("mapping")
+                .help("Put columns in the order the mapping file lists them"),
+        )
+        .arg(
+            Arg::with_name("include")
+                .long("include")
+                .value_name("COLUMN")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only hash this column, given by index or name glob; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("COLUMN")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Do not hash this column, given by index or name glob; may be repeated"),
+        )
+        .arg(
+            Arg::with_name("resolutions")
+                .long("resolutions")
+                .value_name("DIGITS")
+                .takes_value(true)
+                .help("Also hash at these comma-separated significant digits, e.g. 3,5,7"),
+        )
+        .arg(
+            Arg::with_name("tolerance")
+                .long("tolerance")
+                .value_name("MAX_DIGITS")
+                .requires("compare")
+                .takes_value(true)
+                .help("Find the most significant digits, up to MAX_DIGITS, at which each column matches"),
+        )
+        .arg(
+            Arg::with_name("sketch")
+                .long("sketch")
+                .value_name("SKETCH_FILE")
+                .takes_value(true)
+                .help("Write an IBLT sketch of the row hashes, to list differing rows elsewhere"),
+        )
+        .arg(
+            Arg::with_name("sketch_cells")
+                .long("sketch-cells")
+                .value_name("CELLS")
+                .default_value("3000")
+                .takes_value(true)
+                .help("Size of the sketch, about 1.3 cells per differing row to recover"),
+        )
+        .arg(
+            Arg::with_name("diff_sketch")
+                .long("diff-sketch")
+                .value_name("OTHER_SKETCH_FILE")
+                .takes_value(true)
+                .help("List the rows differing from the dataset another sketch was written for"),
+        )
+        .arg(
+            Arg::with_name("minhash")
+                .long("minhash")
+                .value_name("MINHASH_FILE")
+                .takes_value(true)
+                .help("Write a MinHash sketch of the rows, to estimate similarity elsewhere"),
+        )
+        .arg(
+            Arg::with_name("minhash_size")
+                .long("minhash-size")
+                .value_name("K")
+                .default_value("1024")
+                .takes_value(true)
+                .help("Row hashes kept in the MinHash sketch, with an error of about 1/sqrt(K)"),
+        )
+        .arg(
+            Arg::with_name("similarity")
+                .long("similarity")
+                .value_name("OTHER_MINHASH_FILE")
+                .takes_value(true)
+                .help("Estimate the similarity to the dataset another MinHash sketch was written for"),
+        )
+        .arg(
+            Arg::with_name("distinct")
+                .long("distinct")
+                .help("Also estimate the number of distinct rows with a HyperLogLog sketch"),
+        )
+        .arg(
+            Arg::with_name("distinct_precision")
+                .long("distinct-precision")
+                .value_name("PRECISION")
+                .default_value("14")
+                .possible_values(&[
+                    "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16", "17",
+                    "18",
+                ])
+                .hide_possible_values(true)
+                .takes_value(true)
+                .help("HyperLogLog registers as a power of 2, from 4 to 18"),
+        )
+        .arg(
+            Arg::with_name("compare")
+                .long("compare")
+                .value_name("OTHER_FILE")
+                .takes_value(true)
+                .help("Hash a second file with the same settings and report the differences"),
+        )
         .get_matches();
     let start_time = Instant::now();
     let file_path = matches.value_of("input_file").unwrap();
     let truncation = matches.value_of("truncation").unwrap();
     let digits = matches.value_of("digits").unwrap();
     let characters = matches.value_of("characters").unwrap();
-    let inference_rows: usize = matches.value_of("inference_rows").unwrap().parse().unwrap();
+    let inference = match matches.value_of("inference_rows").unwrap() {
+        _ if matches.is_present("text") => SchemaInference::Text,
+        "all" => SchemaInference::Full,
+        rows => SchemaInference::Rows(rows.parse().unwrap()),
+    };
     let batch_size: usize = matches.value_of("batch_size").unwrap().parse().unwrap();
     let n_cpus: usize = matches.value_of("n_cpus").unwrap().parse().unwrap();
-    let config = IdHashConfigBuilder::new()
+    let column_order = match matches.value_of("column_order").unwrap() {
+        "name" => ColumnOrder::ByName,
+        _ => ColumnOrder::Positional,
+    };
+    let multiset = match matches.value_of("multiset").unwrap() {
+        "lthash" => MultisetHash::LtHash,
+        _ => MultisetHash::Sum,
+    };
+    let width = match matches.value_of("width").unwrap() {
+        "256" => Width::W256,
+        "512" => Width::W512,
+        _ => Width::W128,
+    };
+    let version = match matches.value_of("version").unwrap() {
+        "1" => Version::V1,
+        _ => Version::V2,
+    };
+    let mut builder = IdHashConfigBuilder::new();
+    if let Some(name) = matches.value_of("hasher") {
+        builder.hasher(row_hasher(name).unwrap());
+    }
+    builder.column_order(column_order);
+    if matches.is_present("invariant") {
+        builder.fully_invariant(true);
+    }
+    if let Some(key_file) = matches.value_of("key_file") {
+        builder.key(read_key_file(key_file).expect("Failed to read key file"));
+    }
+    let parse_overrides = |name: &str| -> Vec<(&str, &str)> {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(|x| {
+                x.rsplit_once('=')
+                    .expect("Column overrides must be given as COLUMN=VALUE")
+            })
+            .collect()
+    };
+    let mut column_options: BTreeMap<&str, ColumnOptions> = BTreeMap::new();
+    for (column, digits) in parse_overrides("column_digits") {
+        column_options.entry(column).or_default().digits =
+            Some(digits.parse().expect("Invalid column override"));
+    }
+    for (column, characters) in parse_overrides("column_characters") {
+        column_options.entry(column).or_default().characters =
+            Some(characters.parse().expect("Invalid column override"));
+    }
+    for (column, normalizers) in parse_overrides("column_normalize") {
+        column_options.entry(column).or_default().string_normalizers =
+            Some(parse_normalizers(normalizers));
+    }
+    if let Some(normalizers) = matches.value_of("normalize") {
+        builder.string_normalizers(parse_normalizers(normalizers));
+    }
+    for (column, options) in column_options {
+        builder.column_options(column, options);
+    }
+    let mut null_tokens: Vec<String> = matches
+        .values_of("null_token")
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    if matches.is_present("common_nulls") {
+        null_tokens.extend(common_null_tokens());
+    }
+    builder.null_tokens(null_tokens);
+    if let Some(filter) = matches.value_of("filter") {
+        builder.filter(RowFilter::parse(filter).expect("Invalid row filter"));
+    }
+    if let Some(mapping_file) = matches.value_of("mapping") {
+        builder.mapping(
+            ColumnMapping::from_file(mapping_file, matches.is_present("reorder"))
+                .expect("Failed to read mapping file"),
+        );
+    }
+    if let Some(resolutions) = matches.value_of("resolutions") {
+        builder.resolutions(
+            resolutions
+                .split(',')
+                .map(|x| x.trim().parse().expect("Invalid resolution"))
+                .collect(),
+        );
+    }
+    let other_sketch = matches.value_of("diff_sketch").map(|sketch_file| {
+        Iblt::from_bytes(&fs::read(sketch_file).expect("Failed to read sketch file"))
+            .expect("Invalid sketch file")
+    });
+    if let Some(other_sketch) = &other_sketch {
+        builder.sketch(other_sketch.len());
+    } else if matches.is_present("sketch") {
+        builder.sketch(
+            matches
+                .value_of("sketch_cells")
+                .unwrap()
+                .parse()
+                .expect("Invalid sketch size"),
+        );
+    }
+    let other_min_hash = matches.value_of("similarity").map(|min_hash_file| {
+        MinHash::from_bytes(&fs::read(min_hash_file).expect("Failed to read MinHash file"))
+            .expect("Invalid MinHash file")
+    });
+    if matches.is_present("minhash")
+        || matches.is_present("similarity")
+        || matches.is_present("compare")
+    {
+        builder.min_hash(
+            matches
+                .value_of("minhash_size")
+                .unwrap()
+                .parse()
+                .expect("Invalid MinHash size"),
+        );
+    }
+    if matches.is_present("distinct") {
+        builder.hyperloglog(
+            matches
+                .value_of("distinct_precision")
+                .unwrap()
+                .parse()
+                .expect("Invalid HyperLogLog precision"),
+        );
+    }
+    let config = builder
         .truncation(truncation.parse().unwrap())
         .digits(digits.parse().unwrap())
         .characters(characters.parse().unwrap())
-        .build();
-    let csv = CSVReader::new(file_path.to_string(), inference_rows, batch_size);
-    let csv_schema = csv.schema.clone();
-    let res = if n_cpus > 1 {
-        calculate_idhash_par(csv.par_bridge(), csv_schema, config)
-    } else {
-        calculate_idhash(csv, csv_schema, config)
+        .schema(matches.is_present("schema"))
+        .column_fingerprints(
+            matches.is_present("column_fingerprints") || matches.is_present("invariant"),
+        )
+        .min_max(matches.is_present("summary"))
+        .multiset(multiset)
+        .width(width)
+        .version(version)
+        .unf6(matches.is_present("unf"))
+        .order_sensitive(matches.is_present("ordered"))
+        .numeric_invariant(matches.is_present("numeric_invariant"))
+        .build()
+        .unwrap_or_else(|err| exit_with_error("Invalid settings", err));
+    let parse_selectors = |name: &str| -> Vec<ColumnSelector> {
+        matches
+            .values_of(name)
+            .map(|values| {
+                values
+                    .map(|x| ColumnSelector::parse(x).expect("Invalid column pattern"))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let options = ReadOptions {
+        inference,
+        batch_size,
+        n_cpus,
+        selection: ColumnSelection {
+            include: parse_selectors("include"),
+            exclude: parse_selectors("exclude"),
+        },
     };
+    let res = fingerprint_file(file_path, &options, config.clone());
     let end_time = Instant::now();
+    let schema_hash = match res.schema {
+        Some(schema_hash) => format!(" | SchemaHash: {}", schema_hash),
+        None => String::new(),
+    };
+    let distinct_rows = match res.distinct_rows() {
+        Some(distinct_rows) => format!(
+            " | Rows: {} | Distinct Rows: ~{:.0}",
+            res.summary.rows, distinct_rows
+        ),
+        None => String::new(),
+    };
+    // Keep the line in its original form for the default hasher, which
+    // scripts parsing the output expect
+    let hasher = match res.metadata["hasher"].as_str() {
+        "murmur3" => String::new(),
+        hasher => format!(" | Hasher: {}", hasher),
+    };
     println!(
-        "File: {} | ShortHash: {} | Time Taken: {:?}",
+        "File: {} | ShortHash: {}{}{}{} | Time Taken: {:?}",
         file_path,
-        res,
+        res.combined(),
+        schema_hash,
+        distinct_rows,
+        hasher,
         end_time.duration_since(start_time)
     );
+    if let Some(ordered) = res.ordered {
+        println!("OrderedHash: {}", ordered);
+    }
+    if !res.wide.is_empty() {
+        println!("WideHash: {}", res.to_hex());
+    }
+    if let Some(lthash) = &res.lthash {
+        println!("LtHash: {}", lthash.to_hex());
+    }
+    if let Some(unf) = &res.unf {
+        println!("UNF: {}", unf.dataset);
+        for (name, column_unf) in &unf.columns {
+            println!("Column: {} | UNF: {}", name, column_unf);
+        }
+    }
+    for column in &res.columns {
+        println!("Column: {} | ShortHash: {}", column.name, column.hash);
+    }
+    for resolution in &res.resolutions {
+        println!(
+            "Digits: {} | ShortHash: {}",
+            resolution.digits, resolution.data
+        );
+        for column in &resolution.columns {
+            println!(
+                "Digits: {} | Column: {} | ShortHash: {}",
+                resolution.digits, column.name, column.hash
+            );
+        }
+    }
+    if matches.is_present("summary") {
+        println!("{}", res.summary);
+    }
+    if let (Some(sketch_file), Some(sketch)) = (matches.value_of("sketch"), &res.sketch) {
+        fs::write(sketch_file, sketch.to_bytes()).expect("Failed to write sketch file");
+    }
+    if let (Some(min_hash_file), Some(min_hash)) = (matches.value_of("minhash"), &res.min_hash) {
+        fs::write(min_hash_file, min_hash.to_bytes()).expect("Failed to write MinHash file");
+    }
+    if let (Some(other), Some(min_hash)) = (&other_min_hash, &res.min_hash) {
+        let similarity = min_hash
+            .similarity(other)
+            .unwrap_or_else(|err| exit_with_error("Failed to compare MinHash sketches", err));
+        println!("Similarity: {}", similarity);
+    }
+    if let (Some(other_sketch), Some(sketch)) = (other_sketch, res.sketch.clone()) {
+        let difference = sketch
+            .subtract(&other_sketch)
+            .and_then(Iblt::decode)
+            .unwrap_or_else(|err| exit_with_error("Failed to compare sketches", err));
+        let hashes: HashSet<u128> = difference.left_only.iter().copied().collect();
+        let mut config = config.clone();
+        let csv = open_file(file_path, &options, &mut config);
+        let csv_schema = csv.schema.clone();
+        for (row_hash, values) in find_rows(csv, csv_schema, config, &hashes)
+            .unwrap_or_else(|err| exit_with_error(file_path, err))
+        {
+            println!("Only here: {} | Row: {}", row_hash, values);
+        }
+        for row_hash in difference.right_only {
+            println!("Only in other: {}", row_hash);
+        }
+    }
+    if let Some(other_path) = matches.value_of("compare") {
+        let other = fingerprint_file(other_path, &options, config.clone());
+        println!(
+            "File: {} | ShortHash: {} | Comparison: {}",
+            other_path,
+            other.combined(),
+            res.compare(&other)
+        );
+        if let (Some(left), Some(right)) = (&res.min_hash, &other.min_hash) {
+            if res.data != other.data {
+                let similarity = left.similarity(right).unwrap_or_else(|err| {
+                    exit_with_error("Failed to compare MinHash sketches", err)
+                });
+                println!("Similarity: {}", similarity);
+            }
+        }
+        if let Some(max_digits) = matches.value_of("tolerance") {
+            let max_digits: usize = max_digits.parse().expect("Invalid tolerance");
+            let tolerance = find_tolerance(&config, max_digits, |config| {
+                (
+                    fingerprint_file(file_path, &options, config.clone()),
+                    fingerprint_file(other_path, &options, config),
+                )
+            })
+            .unwrap_or_else(|err| exit_with_error("Invalid tolerance", err));
+            match tolerance.digits() {
+                Some(digits) => println!("Tolerance: {} digits | {}", digits, tolerance),
+                None => println!("Tolerance: none | {}", tolerance),
+            }
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use arrow::datatypes::{Field, Schema};
+
+/// Aligns the columns of a dataset with another dataset's headers before hashing,
+/// e.g. `cust_id` is hashed as `customer_id`.
+///
+/// Applied after the ColumnSelection. Source columns that are not present are ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnMapping {
+    /// Source column name and the name to hash it under, in the order listed
+    pub renames: Vec<(String, String)>,
+    /// Arrange the listed columns in the order they were listed, followed by
+    /// any unlisted columns in Schema order
+    pub reorder: bool,
+}
+
+impl ColumnMapping {
+    /// Parse one `source,target` pair per line. A line with a single name keeps
+    /// that column's name, which is useful when reordering. Blank lines and lines
+    /// starting with `#` are ignored.
+    pub fn parse(contents: &str, reorder: bool) -> io::Result<Self> {
+        let mut renames: Vec<(String, String)> = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (source, target) = match line.split_once(',') {
+                Some((source, target)) => (source.trim(), target.trim()),
+                None => (line, line),
+            };
+            if renames.iter().any(|(existing, _)| existing == source) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Column `{}` is mapped more than once", source),
+                ));
+            }
+            renames.push((source.to_string(), target.to_string()));
+        }
+        Ok(ColumnMapping { renames, reorder })
+    }
+
+    pub fn from_file(file_path: &str, reorder: bool) -> io::Result<Self> {
+        ColumnMapping::parse(&fs::read_to_string(file_path)?, reorder)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        !self.reorder && self.renames.iter().all(|(source, target)| source == target)
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.renames.iter().position(|(source, _)| source == name)
+    }
+
+    /// Reorder the column `indices` of `schema`, if reordering was requested
+    pub fn arrange(&self, schema: &Schema, mut indices: Vec<usize>) -> Vec<usize> {
+        if self.reorder {
+            // Stable, so unlisted columns keep their relative order at the end
+            indices.sort_by_key(|&index| {
+                self.position(&schema.fields[index].name)
+                    .unwrap_or(self.renames.len())
+            });
+        }
+        indices
+    }
+
+    /// Rename the fields of `schema`, keeping their types and nullability
+    pub fn rename(&self, schema: &Schema) -> Arc<Schema> {
+        Arc::new(Schema::from(
+            schema
+                .fields
+                .iter()
+                .map(|field| match self.position(&field.name) {
+                    Some(position) => Field {
+                        name: self.renames[position].1.clone(),
+                        ..field.clone()
+                    },
+                    None => field.clone(),
+                })
+                .collect::<Vec<Field>>(),
+        ))
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+/// Identifies a serialized sketch, followed by the format version and the
+/// settings id
+const MAGIC: &[u8; 4] = b"BOTK";
+const FORMAT_VERSION: u8 = 1;
+
+/// Errors from comparing sketches
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MinHashError {
+    /// Sketches written with different settings hash the same rows differently
+    SettingsMismatch,
+}
+
+impl fmt::Display for MinHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinHashError::SettingsMismatch => write!(
+                f,
+                "sketches were written with different settings, e.g. version or hasher"
+            ),
+        }
+    }
+}
+
+impl Error for MinHashError {}
+
+/// Bottom-k MinHash sketch: the `k` smallest distinct row hashes.
+///
+/// Row hashes are uniformly distributed, so the smallest ones are a uniform
+/// sample of the distinct rows. Comparing the samples of two datasets estimates
+/// their Jaccard similarity, with a standard error of about `1 / sqrt(k)`.
+/// Like the data hash, it is independent of row order and sketches of batches
+/// can be merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinHash {
+    /// `IdHashConfig::settings_id` of the config the row hashes come from
+    settings: u64,
+    k: usize,
+    hashes: BTreeSet<u128>,
+}
+
+impl MinHash {
+    pub fn new(k: usize) -> Self {
+        MinHash {
+            settings: 0,
+            k: k.max(1),
+            hashes: BTreeSet::new(),
+        }
+    }
+
+    /// The same sketch, recording the settings its row hashes were computed with
+    pub fn with_settings(mut self, settings: u64) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn settings(&self) -> u64 {
+        self.settings
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Add a row hash
+    pub fn insert(&mut self, hash: u128) {
+        if self.hashes.len() < self.k {
+            self.hashes.insert(hash);
+        } else if self.hashes.last().is_some_and(|&max| hash < max) && self.hashes.insert(hash) {
+            self.hashes.pop_last();
+        }
+    }
+
+    /// Sketch of the union of the rows of two sketches of the same size
+    pub fn merge(mut self, other: &MinHash) -> MinHash {
+        for &hash in &other.hashes {
+            self.insert(hash);
+        }
+        self
+    }
+
+    /// Estimated number of distinct rows, exact when below `k`
+    pub fn distinct_rows(&self) -> f64 {
+        match self.hashes.last() {
+            Some(&max) if self.hashes.len() >= self.k && self.k > 1 => {
+                // The k-th smallest of n uniform values is about k / (n + 1)
+                (self.k - 1) as f64 / (max as f64 / u128::MAX as f64)
+            }
+            _ => self.hashes.len() as f64,
+        }
+    }
+
+    /// Estimate how much the distinct rows of two datasets overlap. Sketches
+    /// of different sizes are compared at the smaller size.
+    pub fn similarity(&self, other: &MinHash) -> Result<Similarity, MinHashError> {
+        if self.settings != other.settings {
+            return Err(MinHashError::SettingsMismatch);
+        }
+        let k = self.k.min(other.k);
+        let left: BTreeSet<u128> = self.hashes.iter().take(k).copied().collect();
+        let right: BTreeSet<u128> = other.hashes.iter().take(k).copied().collect();
+        // The k smallest hashes of the union are a uniform sample of the union
+        let sample: Vec<u128> = left.union(&right).take(k).copied().collect();
+        let shared = sample
+            .iter()
+            .filter(|hash| left.contains(hash) && right.contains(hash))
+            .count();
+        let jaccard = if sample.is_empty() {
+            1.0
+        } else {
+            shared as f64 / sample.len() as f64
+        };
+        let left_rows = self.distinct_rows();
+        let right_rows = other.distinct_rows();
+        Ok(Similarity {
+            jaccard,
+            left_rows,
+            right_rows,
+            // |A ∩ B| = J * |A ∪ B| and |A ∪ B| = |A| + |B| - |A ∩ B|
+            shared_rows: jaccard * (left_rows + right_rows) / (1.0 + jaccard),
+        })
+    }
+
+    /// Compact binary form, which can be stored and later compared
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 17 + self.hashes.len() * 16);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.settings.to_le_bytes());
+        bytes.extend_from_slice(&(self.k as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.hashes.len() as u32).to_le_bytes());
+        for hash in &self.hashes {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<MinHash> {
+        let body = bytes.strip_prefix(MAGIC.as_slice())?;
+        let (&version, body) = body.split_first()?;
+        if version != FORMAT_VERSION || body.len() < 16 {
+            return None;
+        }
+        let settings = u64::from_le_bytes(body[..8].try_into().ok()?);
+        let k = u32::from_le_bytes(body[8..12].try_into().ok()?) as usize;
+        let len = u32::from_le_bytes(body[12..16].try_into().ok()?) as usize;
+        let body = &body[16..];
+        if k == 0 || len > k || body.len() != len * 16 {
+            return None;
+        }
+        let hashes: BTreeSet<u128> = body
+            .chunks_exact(16)
+            .map(|hash| u128::from_le_bytes(hash.try_into().unwrap()))
+            .collect();
+        (hashes.len() == len).then_some(MinHash {
+            settings,
+            k,
+            hashes,
+        })
+    }
+}
+
+/// Estimated overlap of the distinct rows of two datasets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Similarity {
+    /// Shared distinct rows over all distinct rows, between 0 and 1
+    pub jaccard: f64,
+    /// Distinct rows of the first dataset
+    pub left_rows: f64,
+    /// Distinct rows of the second dataset
+    pub right_rows: f64,
+    /// Distinct rows present in both
+    pub shared_rows: f64,
+}
+
+impl fmt::Display for Similarity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Jaccard: {:.4} | Distinct Rows: ~{:.0} vs ~{:.0} | Shared Rows: ~{:.0}",
+            self.jaccard, self.left_rows, self.right_rows, self.shared_rows
+        )
+    }
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fmt;
+
+/// Number of 16-bit lanes in an LtHash state, 2048 bytes in total
+pub const LTHASH_LANES: usize = 1024;
+
+/// Errors from combining multisets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LtHashError {
+    /// Rows expanded with different keys cannot be combined
+    KeyMismatch,
+}
+
+impl fmt::Display for LtHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LtHashError::KeyMismatch => {
+                write!(f, "multisets were expanded with different keys")
+            }
+        }
+    }
+}
+
+impl Error for LtHashError {}
+
+/// Lattice-based homomorphic multiset hash (LtHash16).
+///
+/// Each row is expanded to 2048 bytes with the BLAKE3 XOF, keyed when the row
+/// hash is keyed, and added lane-wise modulo 2^16. Like the default sum of row
+/// hashes it is independent of row order and rows can be added or removed
+/// afterwards, but finding a set of rows that cancel out is as hard as a lattice
+/// short-vector problem.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LtHash {
+    lanes: Box<[u16; LTHASH_LANES]>,
+    /// Key of the BLAKE3 expansion, if any
+    key: Option<[u8; 32]>,
+}
+
+impl Default for LtHash {
+    fn default() -> Self {
+        LtHash::new(None)
+    }
+}
+
+impl fmt::Debug for LtHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LtHash({})", self.to_hex())
+    }
+}
+
+fn expand(row: &[u8], key: Option<&[u8; 32]>) -> [u16; LTHASH_LANES] {
+    let mut bytes = [0; LTHASH_LANES * 2];
+    let mut hasher = match key {
+        Some(key) => blake3::Hasher::new_keyed(key),
+        None => blake3::Hasher::new(),
+    };
+    hasher.update(row).finalize_xof().fill(&mut bytes);
+    let mut lanes = [0; LTHASH_LANES];
+    for (lane, pair) in lanes.iter_mut().zip(bytes.chunks_exact(2)) {
+        *lane = u16::from_le_bytes([pair[0], pair[1]]);
+    }
+    lanes
+}
+
+impl LtHash {
+    /// An empty multiset, whose rows are expanded with keyed BLAKE3 if a key is given
+    pub fn new(key: Option<[u8; 32]>) -> Self {
+        LtHash {
+            lanes: Box::new([0; LTHASH_LANES]),
+            key,
+        }
+    }
+
+    /// Add a row's bytes to the multiset
+    pub fn insert(&mut self, row: &[u8]) {
+        for (acc, x) in self
+            .lanes
+            .iter_mut()
+            .zip(expand(row, self.key.as_ref()).iter())
+        {
+            *acc = acc.wrapping_add(*x);
+        }
+    }
+
+    /// Remove a row's bytes from the multiset
+    pub fn remove(&mut self, row: &[u8]) {
+        for (acc, x) in self
+            .lanes
+            .iter_mut()
+            .zip(expand(row, self.key.as_ref()).iter())
+        {
+            *acc = acc.wrapping_sub(*x);
+        }
+    }
+
+    /// Union of two multisets, which must use the same key
+    pub fn merge(mut self, other: &LtHash) -> Result<LtHash, LtHashError> {
+        if self.key != other.key {
+            return Err(LtHashError::KeyMismatch);
+        }
+        for (acc, x) in self.lanes.iter_mut().zip(other.lanes.iter()) {
+            *acc = acc.wrapping_add(*x);
+        }
+        Ok(self)
+    }
+
+    /// Difference of two multisets, where `other` is contained in `self`. Both
+    /// must use the same key.
+    pub fn subtract(mut self, other: &LtHash) -> Result<LtHash, LtHashError> {
+        if self.key != other.key {
+            return Err(LtHashError::KeyMismatch);
+        }
+        for (acc, x) in self.lanes.iter_mut().zip(other.lanes.iter()) {
+            *acc = acc.wrapping_sub(*x);
+        }
+        Ok(self)
+    }
+
+    /// Full 2048-byte state, which can be stored and later merged or subtracted
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.lanes
+            .iter()
+            .flat_map(|lane| lane.to_le_bytes())
+            .collect()
+    }
+
+    /// Restore a state, along with the key its rows are expanded with
+    pub fn from_bytes(bytes: &[u8], key: Option<[u8; 32]>) -> Option<LtHash> {
+        if bytes.len() != LTHASH_LANES * 2 {
+            return None;
+        }
+        let mut state = LtHash::new(key);
+        for (lane, pair) in state.lanes.iter_mut().zip(bytes.chunks_exact(2)) {
+            *lane = u16::from_le_bytes([pair[0], pair[1]]);
+        }
+        Some(state)
+    }
+
+    /// 256-bit BLAKE3 digest of the state, for publishing
+    pub fn digest(&self) -> [u8; 32] {
+        *blake3::hash(&self.to_bytes()).as_bytes()
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.digest()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+
+/// Opt-in normalization of text values before hashing, to ignore differences
+/// between export tools.
+///
+/// Normalizers are always applied in the order declared here, whatever order
+/// they are configured in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StringNormalizer {
+    /// Remove a leading byte order mark
+    StripBom,
+    /// Convert `\r\n` and `\r` line endings to `\n`
+    LineEndings,
+    /// Remove leading and trailing whitespace
+    Trim,
+    /// Replace each run of whitespace with a single space
+    CollapseWhitespace,
+    /// Compare case-insensitively, by converting to lowercase
+    CaseFold,
+}
+
+impl StringNormalizer {
+    pub const ALL: [StringNormalizer; 5] = [
+        StringNormalizer::StripBom,
+        StringNormalizer::LineEndings,
+        StringNormalizer::Trim,
+        StringNormalizer::CollapseWhitespace,
+        StringNormalizer::CaseFold,
+    ];
+
+    /// Name recorded in the Fingerprint metadata
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringNormalizer::StripBom => "strip_bom",
+            StringNormalizer::LineEndings => "line_endings",
+            StringNormalizer::Trim => "trim",
+            StringNormalizer::CollapseWhitespace => "collapse_whitespace",
+            StringNormalizer::CaseFold => "case_fold",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        StringNormalizer::ALL
+            .iter()
+            .find(|normalizer| normalizer.name() == name)
+            .copied()
+    }
+
+    pub fn apply(&self, x: &str) -> String {
+        match self {
+            StringNormalizer::StripBom => String::from(x.strip_prefix('\u{feff}').unwrap_or(x)),
+            StringNormalizer::LineEndings => x.replace("\r\n", "\n").replace('\r', "\n"),
+            StringNormalizer::Trim => String::from(x.trim()),
+            StringNormalizer::CollapseWhitespace => {
+                let mut collapsed = String::with_capacity(x.len());
+                let mut in_whitespace = false;
+                for c in x.chars() {
+                    if c.is_whitespace() {
+                        if !in_whitespace {
+                            collapsed.push(' ');
+                        }
+                        in_whitespace = true;
+                    } else {
+                        collapsed.push(c);
+                        in_whitespace = false;
+                    }
+                }
+                collapsed
+            }
+            StringNormalizer::CaseFold => x.to_lowercase(),
+        }
+    }
+}
+
+/// Sort and deduplicate normalizers into the order they are applied
+pub fn canonical_normalizers(normalizers: &[StringNormalizer]) -> Vec<StringNormalizer> {
+    let mut normalizers = normalizers.to_vec();
+    normalizers.sort_unstable();
+    normalizers.dedup();
+    normalizers
+}
+
+/// Apply normalizers, which must be in canonical order. Without any, the value
+/// is borrowed as it is rather than copied.
+pub fn normalize_string<'a>(x: &'a str, normalizers: &[StringNormalizer]) -> Cow<'a, str> {
+    if normalizers.is_empty() {
+        return Cow::Borrowed(x);
+    }
+    Cow::Owned(
+        normalizers
+            .iter()
+            .fold(String::from(x), |value, normalizer| {
+                normalizer.apply(&value)
+            }),
+    )
+}
+
+/// Names of the normalizers, as recorded in the Fingerprint metadata
+pub(crate) fn normalizer_names(normalizers: &[StringNormalizer]) -> String {
+    normalizers
+        .iter()
+        .map(StringNormalizer::name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
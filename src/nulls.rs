@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, Float32Array, Float64Array, Utf8Array},
+    bitmap::Bitmap,
+    chunk::Chunk,
+    datatypes::{DataType, Schema},
+};
+
+use crate::utils::ThreadArrayChunk;
+
+/// Tokens often used for missing values, for use with `IdHashConfigBuilder::null_tokens`
+pub fn common_null_tokens() -> Vec<String> {
+    ["", "NA", "N/A", "NaN", "None", "null", "NULL"]
+        .iter()
+        .map(|token| token.to_string())
+        .collect()
+}
+
+/// Whether each value of a column is one of the null tokens. Text is compared
+/// exactly, and floating point NaN matches if `NaN` is a token in any case.
+fn token_mask(col: &dyn Array, data_type: &DataType, tokens: &[String]) -> Option<Vec<bool>> {
+    let is_token = |x: &str| tokens.iter().any(|token| token == x);
+    let nan_is_null = tokens.iter().any(|token| token.eq_ignore_ascii_case("nan"));
+    let col = col.as_any();
+    match data_type {
+        DataType::Utf8 => Some(
+            col.downcast_ref::<Utf8Array<i32>>()
+                .expect("Failed to downcast to Utf-8")
+                .iter()
+                .map(|x| x.is_some_and(is_token))
+                .collect(),
+        ),
+        DataType::LargeUtf8 => Some(
+            col.downcast_ref::<Utf8Array<i64>>()
+                .expect("Failed to downcast to Utf-8")
+                .iter()
+                .map(|x| x.is_some_and(is_token))
+                .collect(),
+        ),
+        DataType::Float32 if nan_is_null => Some(
+            col.downcast_ref::<Float32Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .map(|x| x.is_some_and(|x| x.is_nan()))
+                .collect(),
+        ),
+        DataType::Float64 if nan_is_null => Some(
+            col.downcast_ref::<Float64Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .map(|x| x.is_some_and(|x| x.is_nan()))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Mark the values of a batch matching any of the null tokens as null, so they
+/// are hashed, summarised and filtered exactly like missing values
+pub(crate) fn apply_null_tokens(
+    input: Chunk<ThreadArrayChunk>,
+    schema: &Schema,
+    tokens: &[String],
+) -> Chunk<ThreadArrayChunk> {
+    Chunk::new(
+        input
+            .columns()
+            .iter()
+            .zip(schema.fields.iter())
+            .map(
+                |(col, field)| match token_mask(col.as_ref(), field.data_type(), tokens) {
+                    Some(mask) if mask.iter().any(|is_token| *is_token) => {
+                        let validity: Bitmap = mask
+                            .iter()
+                            .enumerate()
+                            .map(|(index, is_token)| !is_token && col.is_valid(index))
+                            .collect();
+                        ThreadArrayChunk::from(Arc::new(col.with_validity(Some(validity))))
+                    }
+                    _ => ThreadArrayChunk::from(Arc::clone(col)),
+                },
+            )
+            .collect(),
+    )
+}
@@ -0,0 +1,217 @@
+use std::fmt::{self, Debug};
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use siphasher::sip128::{Hasher128, SipHasher24};
+
+/// Hash function applied to the bytes of each row
+pub trait RowHasher: Debug + Send + Sync {
+    /// Name recorded in the Fingerprint
+    fn name(&self) -> &'static str;
+
+    fn hash(&self, bytes: &[u8]) -> u128;
+
+    /// Independent hashes of the same bytes, used for wider accumulators.
+    /// Lane 0 must equal `hash`, so that wider results truncate to narrower ones.
+    fn hash_lanes(&self, bytes: &[u8], lanes: &mut [u128]) {
+        let mut seeded = Vec::with_capacity(bytes.len() + 4);
+        for (lane, out) in lanes.iter_mut().enumerate() {
+            if lane == 0 {
+                *out = self.hash(bytes);
+            } else {
+                seeded.clear();
+                seeded.extend_from_slice(&(lane as u32).to_le_bytes());
+                seeded.extend_from_slice(bytes);
+                *out = self.hash(&seeded);
+            }
+        }
+    }
+
+    /// The same hash function keyed with a secret, if it supports keys
+    fn with_key(&self, _key: &[u8; 32]) -> Option<Arc<dyn RowHasher>> {
+        None
+    }
+
+    /// Public identifier of the secret key in use, if any.
+    /// Lets two parties check they used the same key without revealing it.
+    fn key_id(&self) -> Option<String> {
+        None
+    }
+
+    /// Key for expanding rows into an LtHash, derived from the secret key if
+    /// any, so a keyed LtHash cannot be tested for a given row either
+    fn lthash_key(&self) -> Option<[u8; 32]> {
+        None
+    }
+}
+
+/// Derive the key used to expand rows into an LtHash from a row hash key
+fn lthash_key(key: &[u8]) -> [u8; 32] {
+    blake3::derive_key("idhash 2022 lthash expansion", key)
+}
+
+/// Derive a short identifier from a key, which can be published alongside a Fingerprint
+fn key_id(key: &[u8]) -> String {
+    blake3::derive_key("idhash 2022 key identifier", key)[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// MurmurHash3 x64 128-bit, the original IdHash row hash
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Murmur3;
+
+impl RowHasher for Murmur3 {
+    fn name(&self) -> &'static str {
+        "murmur3"
+    }
+
+    fn hash(&self, bytes: &[u8]) -> u128 {
+        fasthash::murmur3::hash128(bytes)
+    }
+
+    fn hash_lanes(&self, bytes: &[u8], lanes: &mut [u128]) {
+        for (seed, out) in lanes.iter_mut().enumerate() {
+            *out = fasthash::murmur3::hash128_with_seed(bytes, seed as u32);
+        }
+    }
+}
+
+/// XXH3 128-bit
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Xxh3;
+
+impl RowHasher for Xxh3 {
+    fn name(&self) -> &'static str {
+        "xxh3"
+    }
+
+    fn hash(&self, bytes: &[u8]) -> u128 {
+        xxhash_rust::xxh3::xxh3_128(bytes)
+    }
+
+    fn hash_lanes(&self, bytes: &[u8], lanes: &mut [u128]) {
+        for (seed, out) in lanes.iter_mut().enumerate() {
+            *out = xxhash_rust::xxh3::xxh3_128_with_seed(bytes, seed as u64);
+        }
+    }
+}
+
+/// SipHash-2-4 128-bit
+#[derive(Clone, Copy, Default)]
+pub struct SipHash {
+    key: [u8; 16],
+}
+
+impl Debug for SipHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SipHash").finish_non_exhaustive()
+    }
+}
+
+impl SipHash {
+    pub fn new(key: [u8; 16]) -> Self {
+        SipHash { key }
+    }
+}
+
+impl RowHasher for SipHash {
+    fn name(&self) -> &'static str {
+        "siphash"
+    }
+
+    fn hash(&self, bytes: &[u8]) -> u128 {
+        let mut hasher = SipHasher24::new_with_key(&self.key);
+        hasher.write(bytes);
+        hasher.finish128().as_u128()
+    }
+
+    /// Keyed with the first 16 bytes of `key`
+    fn with_key(&self, key: &[u8; 32]) -> Option<Arc<dyn RowHasher>> {
+        let mut sip_key = [0; 16];
+        sip_key.copy_from_slice(&key[..16]);
+        Some(Arc::new(SipHash::new(sip_key)))
+    }
+
+    fn key_id(&self) -> Option<String> {
+        (self.key != [0; 16]).then(|| key_id(&self.key))
+    }
+
+    fn lthash_key(&self) -> Option<[u8; 32]> {
+        (self.key != [0; 16]).then(|| lthash_key(&self.key))
+    }
+}
+
+/// BLAKE3, truncated to 128 bits
+#[derive(Clone, Copy, Default)]
+pub struct Blake3 {
+    key: Option<[u8; 32]>,
+}
+
+impl Blake3 {
+    pub fn keyed(key: [u8; 32]) -> Self {
+        Blake3 { key: Some(key) }
+    }
+}
+
+impl Debug for Blake3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blake3")
+            .field("keyed", &self.key.is_some())
+            .finish()
+    }
+}
+
+impl RowHasher for Blake3 {
+    fn name(&self) -> &'static str {
+        "blake3"
+    }
+
+    fn hash(&self, bytes: &[u8]) -> u128 {
+        let digest = match &self.key {
+            Some(key) => blake3::keyed_hash(key, bytes),
+            None => blake3::hash(bytes),
+        };
+        let mut truncated = [0; 16];
+        truncated.copy_from_slice(&digest.as_bytes()[..16]);
+        u128::from_le_bytes(truncated)
+    }
+
+    /// Consecutive 128-bit blocks of the BLAKE3 extended output
+    fn hash_lanes(&self, bytes: &[u8], lanes: &mut [u128]) {
+        let mut hasher = match &self.key {
+            Some(key) => blake3::Hasher::new_keyed(key),
+            None => blake3::Hasher::new(),
+        };
+        let mut reader = hasher.update(bytes).finalize_xof();
+        let mut block = [0; 16];
+        for out in lanes.iter_mut() {
+            reader.fill(&mut block);
+            *out = u128::from_le_bytes(block);
+        }
+    }
+
+    fn with_key(&self, key: &[u8; 32]) -> Option<Arc<dyn RowHasher>> {
+        Some(Arc::new(Blake3::keyed(*key)))
+    }
+
+    fn key_id(&self) -> Option<String> {
+        self.key.as_ref().map(|key| key_id(key))
+    }
+
+    fn lthash_key(&self) -> Option<[u8; 32]> {
+        self.key.as_ref().map(|key| lthash_key(key))
+    }
+}
+
+/// Look up a built-in RowHasher by the name it records in the Fingerprint
+pub fn row_hasher(name: &str) -> Option<Arc<dyn RowHasher>> {
+    match name {
+        "murmur3" => Some(Arc::new(Murmur3)),
+        "xxh3" => Some(Arc::new(Xxh3)),
+        "siphash" => Some(Arc::new(SipHash::default())),
+        "blake3" => Some(Arc::new(Blake3::default())),
+        _ => None,
+    }
+}
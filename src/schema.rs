@@ -0,0 +1,84 @@
+use arrow::datatypes::{DataType, Field, Schema};
+use fasthash::murmur3::hash128;
+
+use crate::{config::IdHashConfig, hash_builder::column_order};
+
+/// Logical type name used when fingerprinting a Schema.
+///
+/// Physical widths are collapsed, so that an `Int32` column and an `Int64`
+/// column holding the same values describe the same schema.
+pub fn normalized_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Null => String::from("null"),
+        DataType::Boolean => String::from("boolean"),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => {
+            String::from("integer")
+        }
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => {
+            String::from("unsigned")
+        }
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => String::from("float"),
+        DataType::Decimal(precision, scale) => format!("decimal({},{})", precision, scale),
+        DataType::Utf8 | DataType::LargeUtf8 => String::from("string"),
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            String::from("binary")
+        }
+        DataType::Date32 | DataType::Date64 => String::from("date"),
+        DataType::Time32(_) | DataType::Time64(_) => String::from("time"),
+        DataType::Timestamp(_, None) => String::from("timestamp"),
+        DataType::Timestamp(_, Some(tz)) => format!("timestamp({})", tz),
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            format!("list<{}>", normalized_type(field.data_type()))
+        }
+        DataType::Dictionary(_, values, _) => normalized_type(values),
+        DataType::Extension(_, inner, _) => normalized_type(inner),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Integer, unsigned, floating point and decimal types
+pub fn is_numeric(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Decimal(_, _)
+    )
+}
+
+fn field_bytes(field: &Field, config: &IdHashConfig) -> Vec<u8> {
+    let type_name = if config.numeric_invariant && is_numeric(field.data_type()) {
+        String::from("number")
+    } else {
+        normalized_type(field.data_type())
+    };
+    let mut encoded = Vec::with_capacity(field.name.len() + 16);
+    encoded.extend_from_slice(field.name.as_bytes());
+    encoded.push(b'\x00');
+    encoded.extend_from_slice(type_name.as_bytes());
+    encoded.push(b'\x00');
+    encoded.push(field.is_nullable as u8);
+    encoded
+}
+
+/// Produce MurmurHash for the field names, logical types and nullability of a Schema
+///
+/// Fields are taken in the same order as the columns of each row. In numeric
+/// invariant mode every numeric type is described as `number`.
+pub fn schema_hash(schema: &Schema, config: &IdHashConfig) -> u128 {
+    hash128(
+        column_order(schema, config.column_order)
+            .into_iter()
+            .flat_map(|index| field_bytes(&schema.fields[index], config))
+            .collect::<Vec<u8>>(),
+    )
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use arrow::datatypes::Schema;
+use glob::{Pattern, PatternError};
+
+/// Identifies one or more columns of a Schema
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnSelector {
+    /// Position of the column in the Schema
+    Index(usize),
+    /// Glob pattern matched against the column name, e.g. `feature_*`
+    Name(Pattern),
+}
+
+impl ColumnSelector {
+    /// Parse a column index, or otherwise a glob pattern over column names
+    pub fn parse(x: &str) -> Result<Self, PatternError> {
+        match x.parse::<usize>() {
+            Ok(index) => Ok(ColumnSelector::Index(index)),
+            Err(_) => Ok(ColumnSelector::Name(Pattern::new(x)?)),
+        }
+    }
+
+    fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            ColumnSelector::Index(x) => *x == index,
+            ColumnSelector::Name(pattern) => pattern.matches(name),
+        }
+    }
+}
+
+impl fmt::Display for ColumnSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnSelector::Index(index) => write!(f, "{}", index),
+            ColumnSelector::Name(pattern) => write!(f, "{}", pattern.as_str()),
+        }
+    }
+}
+
+/// Columns to hash. By default every column is included.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnSelection {
+    /// Only these columns, or every column if empty
+    pub include: Vec<ColumnSelector>,
+    /// Columns to leave out, even if included
+    pub exclude: Vec<ColumnSelector>,
+}
+
+impl ColumnSelection {
+    pub fn is_all(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Indices of the selected columns, in Schema order
+    pub fn resolve(&self, schema: &Schema) -> Vec<usize> {
+        schema
+            .fields
+            .iter()
+            .enumerate()
+            .filter(|(index, field)| {
+                (self.include.is_empty()
+                    || self
+                        .include
+                        .iter()
+                        .any(|selector| selector.matches(*index, &field.name)))
+                    && !self
+                        .exclude
+                        .iter()
+                        .any(|selector| selector.matches(*index, &field.name))
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
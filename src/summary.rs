@@ -0,0 +1,299 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use arrow::{
+    array::{
+        Array, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array,
+        Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array, Utf8Array,
+    },
+    datatypes::DataType,
+};
+
+/// A single value taken from a column, used to report its bounds
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum Value {
+    Boolean(bool),
+    Integer(i64),
+    UInteger(u64),
+    Float(f64),
+    Utf8(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Boolean(x) => write!(f, "{}", x),
+            Value::Integer(x) => write!(f, "{}", x),
+            Value::UInteger(x) => write!(f, "{}", x),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Utf8(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// Minimum and maximum of a column
+pub type Bounds = Option<(Value, Value)>;
+
+fn bounds_of<T, I>(values: I) -> Bounds
+where
+    T: Into<Value>,
+    I: Iterator<Item = T>,
+{
+    values
+        .map(Into::into)
+        .fold(None, |acc, x| merge_bounds(acc, Some((x.clone(), x))))
+}
+
+/// Widen a pair of bounds to cover both
+pub(crate) fn merge_bounds(left: Bounds, right: Bounds) -> Bounds {
+    match (left, right) {
+        (Some((left_min, left_max)), Some((right_min, right_max))) => Some((
+            if right_min.partial_cmp(&left_min) == Some(Ordering::Less) {
+                right_min
+            } else {
+                left_min
+            },
+            if right_max.partial_cmp(&left_max) == Some(Ordering::Greater) {
+                right_max
+            } else {
+                left_max
+            },
+        )),
+        (bounds, None) | (None, bounds) => bounds,
+    }
+}
+
+impl From<bool> for Value {
+    fn from(x: bool) -> Self {
+        Value::Boolean(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(x: &str) -> Self {
+        Value::Utf8(String::from(x))
+    }
+}
+
+macro_rules! value_from {
+    ($native: ty, $variant: ident, $target: ty) => {
+        impl From<&$native> for Value {
+            fn from(x: &$native) -> Self {
+                Value::$variant(*x as $target)
+            }
+        }
+    };
+}
+
+value_from!(i8, Integer, i64);
+value_from!(i16, Integer, i64);
+value_from!(i32, Integer, i64);
+value_from!(i64, Integer, i64);
+value_from!(u8, UInteger, u64);
+value_from!(u16, UInteger, u64);
+value_from!(u32, UInteger, u64);
+value_from!(u64, UInteger, u64);
+value_from!(f32, Float, f64);
+value_from!(f64, Float, f64);
+
+/// Minimum and maximum of the non-null values of an Array.
+/// NaN is ignored, and types without an ordering produce `None`.
+pub(crate) fn column_bounds(col: &dyn Array, data_type: &DataType) -> Bounds {
+    let col = col.as_any();
+    match data_type {
+        DataType::Boolean => bounds_of(
+            col.downcast_ref::<BooleanArray>()
+                .expect("Failed to downcast to Bool")
+                .iter()
+                .flatten(),
+        ),
+        DataType::Int8 => bounds_of(
+            col.downcast_ref::<Int8Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::Int16 => bounds_of(
+            col.downcast_ref::<Int16Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::Int32 | DataType::Date32 => bounds_of(
+            col.downcast_ref::<Int32Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::Int64 | DataType::Date64 | DataType::Timestamp(_, _) => bounds_of(
+            col.downcast_ref::<Int64Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::UInt8 => bounds_of(
+            col.downcast_ref::<UInt8Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::UInt16 => bounds_of(
+            col.downcast_ref::<UInt16Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::UInt32 => bounds_of(
+            col.downcast_ref::<UInt32Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::UInt64 => bounds_of(
+            col.downcast_ref::<UInt64Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten(),
+        ),
+        DataType::Float32 => bounds_of(
+            col.downcast_ref::<Float32Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten()
+                .filter(|x| !x.is_nan()),
+        ),
+        DataType::Float64 => bounds_of(
+            col.downcast_ref::<Float64Array>()
+                .expect("Failed to Downcast")
+                .iter()
+                .flatten()
+                .filter(|x| !x.is_nan()),
+        ),
+        DataType::Utf8 => bounds_of(
+            col.downcast_ref::<Utf8Array<i32>>()
+                .expect("Failed to downcast to Utf-8")
+                .iter()
+                .flatten(),
+        ),
+        _ => None,
+    }
+}
+
+fn values_of<'a, T, I>(values: I) -> Box<dyn Iterator<Item = Option<Value>> + 'a>
+where
+    T: Into<Value>,
+    I: Iterator<Item = Option<T>> + 'a,
+{
+    Box::new(values.map(|x| x.map(Into::into)))
+}
+
+/// Every value of an Array, with dates and timestamps as their underlying integers.
+/// Types without a Value representation produce `None`.
+pub(crate) fn column_values<'a>(
+    col: &'a dyn Array,
+    data_type: &DataType,
+) -> Option<Box<dyn Iterator<Item = Option<Value>> + 'a>> {
+    let col = col.as_any();
+    Some(match data_type {
+        DataType::Boolean => values_of(
+            col.downcast_ref::<BooleanArray>()
+                .expect("Failed to downcast to Bool")
+                .iter(),
+        ),
+        DataType::Int8 => values_of(
+            col.downcast_ref::<Int8Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::Int16 => values_of(
+            col.downcast_ref::<Int16Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::Int32 | DataType::Date32 => values_of(
+            col.downcast_ref::<Int32Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::Int64 | DataType::Date64 | DataType::Timestamp(_, _) => values_of(
+            col.downcast_ref::<Int64Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::UInt8 => values_of(
+            col.downcast_ref::<UInt8Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::UInt16 => values_of(
+            col.downcast_ref::<UInt16Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::UInt32 => values_of(
+            col.downcast_ref::<UInt32Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::UInt64 => values_of(
+            col.downcast_ref::<UInt64Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::Float32 => values_of(
+            col.downcast_ref::<Float32Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::Float64 => values_of(
+            col.downcast_ref::<Float64Array>()
+                .expect("Failed to Downcast")
+                .iter(),
+        ),
+        DataType::Utf8 => values_of(
+            col.downcast_ref::<Utf8Array<i32>>()
+                .expect("Failed to downcast to Utf-8")
+                .iter(),
+        ),
+        DataType::LargeUtf8 => values_of(
+            col.downcast_ref::<Utf8Array<i64>>()
+                .expect("Failed to downcast to Utf-8")
+                .iter(),
+        ),
+        _ => return None,
+    })
+}
+
+/// Diagnostics for a single column
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnSummary {
+    pub name: String,
+    pub null_count: u64,
+    /// `None` unless min/max were requested, or if the column has no ordered values
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+}
+
+/// Human-readable diagnostics gathered while hashing
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub rows: u64,
+    pub columns: Vec<ColumnSummary>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rows: {}", self.rows)?;
+        for column in &self.columns {
+            write!(
+                f,
+                "\nColumn: {} | Nulls: {}",
+                column.name, column.null_count
+            )?;
+            if let (Some(min), Some(max)) = (&column.min, &column.max) {
+                write!(f, " | Min: {} | Max: {}", min, max)?;
+            }
+        }
+        Ok(())
+    }
+}
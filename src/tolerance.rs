@@ -0,0 +1,159 @@
+use std::fmt;
+
+use crate::{
+    config::{ConfigError, IdHashConfig},
+    fingerprint::{ColumnFingerprint, Fingerprint},
+};
+
+/// Largest number of significant digits at which a column matches in two datasets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnTolerance {
+    pub name: String,
+    /// `None` when the column differs even at 1 significant digit, or only
+    /// exists in one of the datasets
+    pub digits: Option<usize>,
+}
+
+/// Outcome of a tolerance search between two datasets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tolerance {
+    /// Every column of the first dataset, in the order they were hashed
+    pub columns: Vec<ColumnTolerance>,
+}
+
+impl Tolerance {
+    /// Largest number of digits at which every column matches, the minimum over
+    /// the columns
+    pub fn digits(&self) -> Option<usize> {
+        self.columns
+            .iter()
+            .map(|column| column.digits)
+            .min()
+            .flatten()
+    }
+}
+
+impl fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| match column.digits {
+                Some(digits) => format!("`{}` to {} digits", column.name, digits),
+                None => format!("`{}` differs", column.name),
+            })
+            .collect();
+        write!(f, "{}", columns.join(", "))
+    }
+}
+
+/// Search range of a single column, the answer lying in `low..=high`
+#[derive(Clone, Copy)]
+struct Search {
+    /// Largest digits known to match, 0 if none is known yet
+    low: usize,
+    high: usize,
+}
+
+impl Search {
+    fn is_done(&self) -> bool {
+        self.low >= self.high
+    }
+
+    /// Digits to hash the column at next, never 0
+    fn probe(&self) -> usize {
+        ((self.low + self.high + 1) / 2).max(1)
+    }
+}
+
+fn column_hash(columns: &[ColumnFingerprint], name: &str) -> Option<u128> {
+    columns
+        .iter()
+        .find(|column| column.name == name)
+        .map(|column| column.hash)
+}
+
+/// Find the largest `digits`, up to `max_digits`, at which each column of two
+/// datasets still has the same column fingerprint.
+///
+/// `fingerprint` hashes both datasets with the given config. Every column is
+/// binary searched at once, by giving each its own digits through
+/// `ColumnOptions`, so only about `log2(max_digits)` passes over each dataset
+/// are needed. This assumes a column matching at some digits also matches at
+/// fewer, which rounding can break near a boundary, e.g. 1.45 and 1.449.
+/// Columns that are not numeric match at every digits or at none.
+///
+/// # Errors
+/// If `max_digits` is 0, or the config uses version 1. Nothing is hashed in
+/// that case.
+pub fn find_tolerance<F>(
+    config: &IdHashConfig,
+    max_digits: usize,
+    mut fingerprint: F,
+) -> Result<Tolerance, ConfigError>
+where
+    F: FnMut(IdHashConfig) -> (Fingerprint, Fingerprint),
+{
+    let mut config = config.with_digits(max_digits)?;
+    config.column_fingerprints = true;
+    let mut names: Vec<String> = vec![];
+    let mut searches: Vec<Search> = vec![];
+    loop {
+        let mut probe = config.clone();
+        for (name, search) in names.iter().zip(searches.iter()) {
+            probe.column_options.entry(name.clone()).or_default().digits = Some(search.probe());
+        }
+        let (left, right) = fingerprint(probe);
+        if names.is_empty() {
+            // The first pass, at `max_digits`, names the columns
+            names = left
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect();
+            searches = left
+                .columns
+                .iter()
+                .map(|column| {
+                    if column_hash(&right.columns, &column.name) == Some(column.hash) {
+                        Search {
+                            low: max_digits,
+                            high: max_digits,
+                        }
+                    } else {
+                        Search {
+                            low: 0,
+                            high: max_digits.saturating_sub(1),
+                        }
+                    }
+                })
+                .collect();
+        } else {
+            for (name, search) in names.iter().zip(searches.iter_mut()) {
+                if search.is_done() {
+                    continue;
+                }
+                let digits = search.probe();
+                let hash = column_hash(&left.columns, name);
+                if hash.is_some() && hash == column_hash(&right.columns, name) {
+                    search.low = digits;
+                } else {
+                    search.high = digits - 1;
+                }
+            }
+        }
+        if searches.iter().all(Search::is_done) {
+            break;
+        }
+    }
+    Ok(Tolerance {
+        columns: names
+            .into_iter()
+            .zip(searches)
+            .map(|(name, search)| ColumnTolerance {
+                name,
+                digits: (search.low > 0).then_some(search.low),
+            })
+            .collect(),
+    })
+}
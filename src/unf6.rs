@@ -0,0 +1,282 @@
+use arrow::{
+    array::{
+        Array, BooleanArray, Float32Array, Float64Array, Int128Array, Int16Array, Int32Array,
+        Int64Array, Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array, Utf8Array,
+    },
+    chunk::Chunk,
+    datatypes::{DataType, Schema, TimeUnit},
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use sha2::{Digest, Sha256};
+
+use crate::{config::IdHashConfig, unf_vector::UNFVector, utils::ThreadArrayChunk};
+
+/// Days between 0001-01-01 and 1970-01-01
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+/// Genuine UNF version 6 values, as produced by Dataverse
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unf6 {
+    /// UNF of every column, in Schema order
+    pub columns: Vec<(String, String)>,
+    /// UNF of the whole dataset, combining the column UNFs
+    pub dataset: String,
+}
+
+fn format_date(days: i32) -> Option<String> {
+    NaiveDate::from_num_days_from_ce_opt(days + UNIX_EPOCH_DAYS_FROM_CE)
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+/// ISO 8601 with trailing zeros removed from the fractional seconds,
+/// and a `Z` suffix for timestamps with a timezone, which are stored as UTC.
+fn format_timestamp(value: i64, unit: &TimeUnit, utc: bool) -> Option<String> {
+    let (per_second, nanos_per_unit) = match unit {
+        TimeUnit::Second => (1, 1_000_000_000),
+        TimeUnit::Millisecond => (1_000, 1_000_000),
+        TimeUnit::Microsecond => (1_000_000, 1_000),
+        TimeUnit::Nanosecond => (1_000_000_000, 1),
+    };
+    let seconds = value.div_euclid(per_second);
+    let nanos = (value.rem_euclid(per_second) * nanos_per_unit) as u32;
+    NaiveDateTime::from_timestamp_opt(seconds, nanos).map(|timestamp| {
+        let mut formatted = timestamp.format("%Y-%m-%dT%H:%M:%S").to_string();
+        if nanos > 0 {
+            let fraction = format!("{:09}", nanos);
+            formatted.push('.');
+            formatted.push_str(fraction.trim_end_matches('0'));
+        }
+        if utc {
+            formatted.push('Z');
+        }
+        formatted
+    })
+}
+
+/// Whether UNF version 6 defines a normalized form for values of a type
+pub(crate) fn has_unf6_form(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Boolean
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Decimal(_, _)
+            | DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Date32
+            | DataType::Date64
+            | DataType::Timestamp(_, _)
+    )
+}
+
+/// Values of a column normalized as specified by UNF version 6
+///
+/// Columns of other types than those of `has_unf6_form` are rejected with
+/// `FingerprintError::UnsupportedType` before any batch is hashed, so never
+/// reach this.
+pub(crate) fn column_to_unf6<'a>(
+    col: &'a dyn Array,
+    data_type: &DataType,
+    digits: usize,
+) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+    let col = col.as_any();
+    match data_type {
+        DataType::Boolean => col
+            .downcast_ref::<BooleanArray>()
+            .expect("Failed to downcast to Bool")
+            .to_unf6(digits),
+        DataType::Int8 => col
+            .downcast_ref::<Int8Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::Int16 => col
+            .downcast_ref::<Int16Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::Int32 => col
+            .downcast_ref::<Int32Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::Int64 => col
+            .downcast_ref::<Int64Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::UInt8 => col
+            .downcast_ref::<UInt8Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::UInt16 => col
+            .downcast_ref::<UInt16Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::UInt32 => col
+            .downcast_ref::<UInt32Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::UInt64 => col
+            .downcast_ref::<UInt64Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::Float32 => col
+            .downcast_ref::<Float32Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::Float64 => col
+            .downcast_ref::<Float64Array>()
+            .expect("Failed to Downcast")
+            .to_unf6(digits),
+        DataType::Decimal(_, _) => col
+            .downcast_ref::<Int128Array>()
+            .expect("Failed to downcast Decimal to Int128")
+            .to_unf6(digits),
+        DataType::Utf8 => col
+            .downcast_ref::<Utf8Array<i32>>()
+            .expect("Failed to downcast to Utf-8")
+            .to_unf6(digits),
+        DataType::LargeUtf8 => col
+            .downcast_ref::<Utf8Array<i64>>()
+            .expect("Failed to downcast to Utf-8")
+            .to_unf6(digits),
+        DataType::Date32 => Box::new(
+            col.downcast_ref::<Int32Array>()
+                .expect("Failed to downcast Date to Int32")
+                .iter()
+                .map(|x| x.and_then(|days| format_date(*days))),
+        ),
+        DataType::Date64 => Box::new(
+            col.downcast_ref::<Int64Array>()
+                .expect("Failed to downcast Date to Int64")
+                .iter()
+                .map(|x| x.and_then(|millis| format_date(millis.div_euclid(86_400_000) as i32))),
+        ),
+        DataType::Timestamp(unit, timezone) => {
+            let unit = *unit;
+            let utc = timezone.is_some();
+            Box::new(
+                col.downcast_ref::<Int64Array>()
+                    .expect("Failed to Downcast to Int64Array")
+                    .iter()
+                    .map(move |x| x.and_then(|value| format_timestamp(*value, &unit, utc))),
+            )
+        }
+        other => unreachable!("UNF is not defined for {:?}", other),
+    }
+}
+
+/// `UNF:6:` followed by any non-default parameters, e.g. `UNF:6:N9:`
+fn unf6_header(digits: usize, characters: usize, truncation: usize) -> String {
+    let mut parameters = Vec::new();
+    if digits != 7 {
+        parameters.push(format!("N{}", digits));
+    }
+    if characters != 128 {
+        parameters.push(format!("X{}", characters));
+    }
+    if truncation != 128 {
+        parameters.push(format!("H{}", truncation));
+    }
+    if parameters.is_empty() {
+        String::from("UNF:6:")
+    } else {
+        format!("UNF:6:{}:", parameters.join(","))
+    }
+}
+
+/// Append a normalized value, truncated to `characters` and terminated by `\n\0`,
+/// or three null bytes for a missing value
+fn push_unf6_value(hasher: &mut Sha256, value: Option<&str>, characters: usize) {
+    match value {
+        Some(value) => {
+            let end = value
+                .char_indices()
+                .nth(characters)
+                .map_or(value.len(), |(index, _)| index);
+            hasher.update(&value.as_bytes()[..end]);
+            hasher.update(b"\n\x00");
+        }
+        None => hasher.update(b"\x00\x00\x00"),
+    }
+}
+
+fn finish_unf6(hasher: Sha256, digits: usize, characters: usize, truncation: usize) -> String {
+    let digest = hasher.finalize();
+    let bytes = (truncation / 8).clamp(1, digest.len());
+    format!(
+        "{}{}",
+        unf6_header(digits, characters, truncation),
+        base64::encode(&digest[..bytes])
+    )
+}
+
+/// Builds the UNF of each column incrementally.
+///
+/// Unlike the IdHash, a UNF depends on row order, so batches must be supplied in order.
+pub struct Unf6Builder {
+    columns: Vec<Sha256>,
+}
+
+impl Unf6Builder {
+    pub fn new(schema: &Schema) -> Self {
+        Unf6Builder {
+            columns: schema.fields.iter().map(|_| Sha256::new()).collect(),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        input: &Chunk<ThreadArrayChunk>,
+        schema: &Schema,
+        config: &IdHashConfig,
+    ) {
+        for ((hasher, col), field) in self
+            .columns
+            .iter_mut()
+            .zip(input.columns())
+            .zip(schema.fields.iter())
+        {
+            let characters = config.characters_for(&field.name);
+            let digits = config.digits_for(&field.name);
+            for value in column_to_unf6(col.as_ref(), field.data_type(), digits) {
+                push_unf6_value(hasher, value.as_deref(), characters);
+            }
+        }
+    }
+
+    /// Produce the column UNFs, each headed by its own parameters, and the dataset
+    /// UNF by sorting the column UNFs and taking the UNF of them as a vector of
+    /// character strings
+    pub fn finish(self, schema: &Schema, config: &IdHashConfig) -> Unf6 {
+        let columns: Vec<(String, String)> = self
+            .columns
+            .into_iter()
+            .zip(schema.fields.iter())
+            .map(|(hasher, field)| {
+                let unf = finish_unf6(
+                    hasher,
+                    config.digits_for(&field.name),
+                    config.characters_for(&field.name),
+                    config.truncation,
+                );
+                (field.name.clone(), unf)
+            })
+            .collect();
+        let mut sorted: Vec<&str> = columns.iter().map(|(_, unf)| unf.as_str()).collect();
+        sorted.sort_unstable();
+        let mut hasher = Sha256::new();
+        for unf in sorted {
+            push_unf6_value(&mut hasher, Some(unf), config.characters);
+        }
+        Unf6 {
+            dataset: finish_unf6(hasher, config.digits, config.characters, config.truncation),
+            columns,
+        }
+    }
+}
@@ -1,9 +1,15 @@
 use arrow::array::{
-    BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, UInt16Array,
-    UInt32Array, UInt64Array, Utf8Array,
+    BooleanArray, Float32Array, Float64Array, Int128Array, Int16Array, Int32Array, Int64Array,
+    Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array, Utf8Array,
 };
+use arrow::datatypes::DataType;
+use arrow::types::Offset;
 
-const NULL_STRING: &str = "null";
+use crate::normalize::{normalize_string, StringNormalizer};
+
+/// Encoding of a missing value in the version 1 row encoding, the same as the
+/// string `null`. Version 2 tags missing values so they cannot collide.
+pub const LEGACY_NULL_BYTES: &[u8] = b"null\n\x00";
 
 /// Convertible to a Univerally Comparable Vector
 pub trait UNFVector {
@@ -15,21 +21,87 @@ pub trait UNFVector {
         has_nulls: bool,
     ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
         let unf_digits = self.to_unf(digits, has_nulls);
-        Box::new(unf_digits.into_iter().map(move |x| {
-            let mut encoded_string: Vec<u8> = Vec::with_capacity(characters + 4);
-            for item in x.chars().take(characters) {
-                encoded_string.push(item as u8);
-            }
-            encoded_string.push(b'\n');
-            encoded_string.push(b'\x00');
-            encoded_string.into_iter().map(|x| x as u8).collect()
+        Box::new(unf_digits.into_iter().map(move |x| match x {
+            Some(x) => encode_value(&x, characters),
+            None => LEGACY_NULL_BYTES.to_vec(),
         }))
     }
+    /// Provide a Vector of Bytes for each value normalized as specified by UNF
+    /// version 6, so equal numbers are encoded equally whatever their type
+    fn raw_unf6<'a>(
+        &'a self,
+        characters: usize,
+        digits: usize,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+        Box::new(self.to_unf6(digits).map(move |x| match x {
+            Some(x) => encode_value(&x, characters),
+            None => LEGACY_NULL_BYTES.to_vec(),
+        }))
+    }
+    /// Provide a Vector of Bytes for each value, with text normalized first.
+    /// Only text columns are affected.
+    fn raw_normalized<'a>(
+        &'a self,
+        characters: usize,
+        digits: usize,
+        has_nulls: bool,
+        _normalizers: Vec<StringNormalizer>,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+        self.raw(characters, digits, has_nulls)
+    }
+    /// Normalized values, `None` for missing values
     fn to_unf<'a>(
         &'a self,
         _digits: usize,
         has_nulls: bool,
-    ) -> Box<dyn Iterator<Item = String> + 'a>;
+    ) -> Box<dyn Iterator<Item = Option<String>> + 'a>;
+    /// Values normalized as specified by UNF version 6, `None` for missing values
+    fn to_unf6<'a>(&'a self, digits: usize) -> Box<dyn Iterator<Item = Option<String>> + 'a>;
+}
+
+fn encode_value(x: &str, characters: usize) -> Vec<u8> {
+    let mut encoded_string: Vec<u8> = Vec::with_capacity(characters + 4);
+    for item in x.chars().take(characters) {
+        encoded_string.push(item as u8);
+    }
+    encoded_string.push(b'\n');
+    encoded_string.push(b'\x00');
+    encoded_string
+}
+
+/// Normalize a number as specified by UNF version 6: rounded to `digits`
+/// significant digits, in exponential notation with a leading sign, no trailing
+/// zeros in the mantissa and no leading zeros in the exponent, e.g. `+1.234568e+`
+/// for 1.23456789 and `-3.e-3` for -0.003.
+pub fn unf6_number(value: f64, digits: usize) -> String {
+    if value.is_nan() {
+        return String::from("+nan");
+    }
+    if value.is_infinite() {
+        return String::from(if value > 0_f64 { "+inf" } else { "-inf" });
+    }
+    let sign = if value.is_sign_negative() { '-' } else { '+' };
+    let formatted = format!("{:.*e}", digits.saturating_sub(1), value.abs());
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("Exponential format has an exponent");
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0')
+    } else {
+        mantissa
+    };
+    let exponent: i32 = exponent.parse().expect("Exponent is an integer");
+    format!(
+        "{}{}{}e{}{}",
+        sign,
+        mantissa,
+        if mantissa.contains('.') { "" } else { "." },
+        if exponent < 0 { '-' } else { '+' },
+        match exponent {
+            0 => String::new(),
+            x => x.abs().to_string(),
+        }
+    )
 }
 
 pub fn sigfig(value: f64, digits: usize) -> usize {
@@ -44,19 +116,26 @@ impl UNFVector for Float64Array {
         &'a self,
         digits: usize,
         has_null: bool,
-    ) -> Box<dyn Iterator<Item = String> + 'a> {
+    ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
         if has_null {
-            Box::new(self.into_iter().map(move |x| match x {
-                Some(val) => format!("{:e}", sigfig(*val, digits - 1)),
-                None => String::from(NULL_STRING),
-            }))
+            Box::new(
+                self.into_iter()
+                    .map(move |x| x.map(|val| format!("{:e}", sigfig(*val, digits - 1)))),
+            )
         } else {
             Box::new(
                 self.into_iter()
-                    .map(move |x| format!("{:e}", (sigfig(*x.unwrap(), digits - 1)))),
+                    .map(move |x| Some(format!("{:e}", (sigfig(*x.unwrap(), digits - 1))))),
             )
         }
     }
+
+    fn to_unf6<'a>(&'a self, digits: usize) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+        Box::new(
+            self.into_iter()
+                .map(move |x| x.map(|val| unf6_number(*val, digits))),
+        )
+    }
 }
 
 impl UNFVector for Float32Array {
@@ -64,19 +143,26 @@ impl UNFVector for Float32Array {
         &'a self,
         digits: usize,
         has_null: bool,
-    ) -> Box<dyn Iterator<Item = String> + 'a> {
+    ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
         if has_null {
-            Box::new(self.into_iter().map(move |x| match x {
-                Some(val) => format!("{:e}", (sigfig(*val as f64, digits - 1))),
-                None => String::from(NULL_STRING),
-            }))
+            Box::new(
+                self.into_iter()
+                    .map(move |x| x.map(|val| format!("{:e}", (sigfig(*val as f64, digits - 1))))),
+            )
         } else {
             Box::new(
                 self.into_iter()
-                    .map(move |x| format!("{:e}", sigfig(*x.unwrap() as f64, digits - 1))),
+                    .map(move |x| Some(format!("{:e}", sigfig(*x.unwrap() as f64, digits - 1)))),
             )
         }
     }
+
+    fn to_unf6<'a>(&'a self, digits: usize) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+        Box::new(
+            self.into_iter()
+                .map(move |x| x.map(|val| unf6_number(*val as f64, digits))),
+        )
+    }
 }
 
 macro_rules! integer_unf {
@@ -87,60 +173,145 @@ macro_rules! integer_unf {
                 &'a self,
                 _digits: usize,
                 has_null: bool,
-            ) -> Box<dyn Iterator<Item = String> + 'a> {
+            ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
                 if has_null {
-                    Box::new(self.into_iter().map(|x| match x {
-                        Some(val) => val.to_string(),
-                        None => String::from(NULL_STRING),
-                    }))
+                    Box::new(self.into_iter().map(|x| x.map(|val| val.to_string())))
                 } else {
-                    Box::new(self.into_iter().map(|x| x.unwrap().to_string()))
+                    Box::new(self.into_iter().map(|x| Some(x.unwrap().to_string())))
                 }
             }
+
+            fn to_unf6<'a>(
+                &'a self,
+                digits: usize,
+            ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+                Box::new(
+                    self.into_iter()
+                        .map(move |x| x.map(|val| unf6_number(*val as f64, digits))),
+                )
+            }
         }
     };
 }
 
+integer_unf!(Int8Array);
 integer_unf!(Int16Array);
 integer_unf!(Int32Array);
 integer_unf!(Int64Array);
 
+integer_unf!(UInt8Array);
 integer_unf!(UInt16Array);
 integer_unf!(UInt32Array);
 integer_unf!(UInt64Array);
 
+/// Exact decimal representation of a Decimal value, e.g. `-0.05` for -5 at scale 2
+fn format_decimal(value: i128, scale: usize) -> String {
+    if scale == 0 {
+        return value.to_string();
+    }
+    let digits = format!("{:0>width$}", value.unsigned_abs(), width = scale + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", if value < 0 { "-" } else { "" }, whole, fraction)
+}
+
+/// Decimals are stored as integers scaled by a power of ten
+impl UNFVector for Int128Array {
+    fn to_unf<'a>(
+        &'a self,
+        _digits: usize,
+        has_null: bool,
+    ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+        let scale = decimal_scale(self.data_type());
+        if has_null {
+            Box::new(
+                self.into_iter()
+                    .map(move |x| x.map(|val| format_decimal(*val, scale))),
+            )
+        } else {
+            Box::new(
+                self.into_iter()
+                    .map(move |x| Some(format_decimal(*x.unwrap(), scale))),
+            )
+        }
+    }
+
+    fn to_unf6<'a>(&'a self, digits: usize) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+        let divisor = 10_f64.powi(decimal_scale(self.data_type()) as i32);
+        Box::new(
+            self.into_iter()
+                .map(move |x| x.map(|val| unf6_number(*val as f64 / divisor, digits))),
+        )
+    }
+}
+
+fn decimal_scale(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::Decimal(_, scale) => *scale,
+        _ => 0,
+    }
+}
+
+fn raw_normalized_utf8<O: Offset>(
+    array: &Utf8Array<O>,
+    characters: usize,
+    normalizers: Vec<StringNormalizer>,
+) -> Box<dyn Iterator<Item = Vec<u8>> + '_> {
+    Box::new(array.iter().map(move |x| match x {
+        Some(x) => encode_value(&normalize_string(x, &normalizers), characters),
+        None => LEGACY_NULL_BYTES.to_vec(),
+    }))
+}
+
 impl UNFVector for Utf8Array<i32> {
+    fn raw_normalized<'a>(
+        &'a self,
+        characters: usize,
+        _digits: usize,
+        _has_nulls: bool,
+        normalizers: Vec<StringNormalizer>,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+        raw_normalized_utf8(self, characters, normalizers)
+    }
     fn to_unf<'a>(
         &'a self,
         _digits: usize,
         has_null: bool,
-    ) -> Box<dyn Iterator<Item = String> + 'a> {
+    ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
         if has_null {
-            Box::new(self.into_iter().map(|x| match x {
-                Some(val) => String::from(val),
-                None => String::from(NULL_STRING),
-            }))
+            Box::new(self.into_iter().map(|x| x.map(String::from)))
         } else {
-            Box::new(self.into_iter().map(|x| String::from(x.unwrap())))
+            Box::new(self.into_iter().map(|x| Some(String::from(x.unwrap()))))
         }
     }
+    fn to_unf6<'a>(&'a self, _digits: usize) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+        Box::new(self.into_iter().map(|x| x.map(String::from)))
+    }
 }
 
 impl UNFVector for Utf8Array<i64> {
+    fn raw_normalized<'a>(
+        &'a self,
+        characters: usize,
+        _digits: usize,
+        _has_nulls: bool,
+        normalizers: Vec<StringNormalizer>,
+    ) -> Box<dyn Iterator<Item = Vec<u8>> + 'a> {
+        raw_normalized_utf8(self, characters, normalizers)
+    }
     fn to_unf<'a>(
         &'a self,
         _digits: usize,
         has_null: bool,
-    ) -> Box<dyn Iterator<Item = String> + 'a> {
+    ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
         if has_null {
-            Box::new(self.into_iter().map(|x| match x {
-                Some(val) => String::from(val),
-                None => String::from(NULL_STRING),
-            }))
+            Box::new(self.into_iter().map(|x| x.map(String::from)))
         } else {
-            Box::new(self.into_iter().map(|x| String::from(x.unwrap())))
+            Box::new(self.into_iter().map(|x| Some(String::from(x.unwrap()))))
         }
     }
+    fn to_unf6<'a>(&'a self, _digits: usize) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+        Box::new(self.into_iter().map(|x| x.map(String::from)))
+    }
 }
 
 impl UNFVector for BooleanArray {
@@ -148,20 +319,27 @@ impl UNFVector for BooleanArray {
         &'a self,
         _digits: usize,
         has_null: bool,
-    ) -> Box<dyn Iterator<Item = String> + 'a> {
+    ) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
         if has_null {
             Box::new(self.into_iter().map(|x| match x {
                 Some(val) => match val {
-                    true => String::from("true"),
-                    false => String::from("false"),
+                    true => Some(String::from("true")),
+                    false => Some(String::from("false")),
                 },
-                None => String::from(NULL_STRING),
+                None => None,
             }))
         } else {
             Box::new(self.into_iter().map(|x| match x.unwrap() {
-                true => String::from("true"),
-                false => String::from("false"),
+                true => Some(String::from("true")),
+                false => Some(String::from("false")),
             }))
         }
     }
+    /// Booleans are treated as the numbers 1 and 0
+    fn to_unf6<'a>(&'a self, digits: usize) -> Box<dyn Iterator<Item = Option<String>> + 'a> {
+        Box::new(
+            self.into_iter()
+                .map(move |x| x.map(|val| unf6_number(val as u8 as f64, digits))),
+        )
+    }
 }
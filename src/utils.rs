@@ -1,47 +1,140 @@
-use std::fs::File;
+use std::fs::{self, File};
+use std::io;
 
 use std::ops::Deref;
 use std::sync::Arc;
 
 use arrow::array::Array;
 use arrow::chunk::Chunk;
-use arrow::datatypes::Schema;
+use arrow::datatypes::{DataType, Field, Schema};
 use arrow::io::csv::read::{
     deserialize_batch, deserialize_column, infer, infer_schema, ByteRecord,
 };
 use arrow::io::csv::read::{Reader, ReaderBuilder};
 
+use crate::selection::ColumnSelection;
+
+/// How the column types of a CSV file are determined
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaInference {
+    /// Infer from the first rows, which is fast but depends on the order of the rows
+    Rows(usize),
+    /// Infer from every row in a pre-pass over the file
+    Full,
+    /// Read every column as text, so fields are hashed as written whatever they contain
+    Text,
+}
+
 pub struct CSVReader {
     reader: Reader<File>,
     buffer: Vec<ByteRecord>,
     pub batch_size: usize,
     pub schema: Arc<Schema>,
+    /// Fields of the file itself, before any projection
+    fields: Vec<Field>,
+    projection: Option<Vec<usize>>,
     exhausted: bool,
     line_number: usize,
 }
 
 impl CSVReader {
     pub fn new(file_path: String, lines_for_type_inference: usize, batch_size: usize) -> Self {
+        CSVReader::with_inference(
+            file_path,
+            SchemaInference::Rows(lines_for_type_inference),
+            batch_size,
+        )
+    }
+
+    pub fn with_inference(
+        file_path: String,
+        inference: SchemaInference,
+        batch_size: usize,
+    ) -> Self {
         let mut reader = ReaderBuilder::new()
             .has_headers(true)
             .from_path(file_path)
             .unwrap();
 
-        let schema = Arc::new(Schema::from(
-            infer_schema(&mut reader, Some(lines_for_type_inference), true, &infer)
+        let fields = match inference {
+            SchemaInference::Rows(rows) => {
+                infer_schema(&mut reader, Some(rows), true, &infer)
+                    .unwrap()
+                    .0
+            }
+            SchemaInference::Full => infer_schema(&mut reader, None, true, &infer).unwrap().0,
+            SchemaInference::Text => reader
+                .headers()
                 .unwrap()
-                .0,
-        ));
+                .iter()
+                .map(|name| Field::new(name, DataType::Utf8, true))
+                .collect(),
+        };
 
         CSVReader {
             reader,
             buffer: vec![ByteRecord::default(); batch_size],
             batch_size,
             line_number: 0,
-            schema,
+            schema: Arc::new(Schema::from(fields.clone())),
+            fields,
+            projection: None,
             exhausted: false,
         }
     }
+
+    /// Only deserialize the selected columns, so excluded columns are never decoded.
+    /// `schema` is narrowed to the selected columns.
+    pub fn project(&mut self, selection: &ColumnSelection) {
+        let schema = Schema::from(self.fields.clone());
+        let indices = selection.resolve(&schema);
+        self.schema = project_schema(&schema, &indices);
+        self.projection = Some(indices);
+    }
+}
+
+/// Narrow a Schema to the columns at `indices`
+pub fn project_schema(schema: &Schema, indices: &[usize]) -> Arc<Schema> {
+    Arc::new(Schema::from(
+        indices
+            .iter()
+            .map(|&index| schema.fields[index].clone())
+            .collect::<Vec<Field>>(),
+    ))
+}
+
+/// Narrow a RecordBatch to the columns at `indices`, without copying the data
+pub fn project_chunk(input: Chunk<ThreadArrayChunk>, indices: &[usize]) -> Chunk<ThreadArrayChunk> {
+    let columns = input.columns();
+    Chunk::new(
+        indices
+            .iter()
+            .map(|&index| ThreadArrayChunk::from(Arc::clone(&columns[index])))
+            .collect(),
+    )
+}
+
+/// Read a 32-byte secret key, stored either as raw bytes or as 64 hexadecimal characters
+pub fn read_key_file(file_path: &str) -> io::Result<[u8; 32]> {
+    let contents = fs::read(file_path)?;
+    let mut key = [0; 32];
+    if contents.len() == 32 {
+        key.copy_from_slice(&contents);
+        return Ok(key);
+    }
+    let hex = String::from_utf8_lossy(&contents);
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Key must be 32 bytes or 64 hexadecimal characters",
+        ));
+    }
+    for (byte, index) in key.iter_mut().zip((0..64).step_by(2)) {
+        *byte = u8::from_str_radix(&hex[index..index + 2], 16)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    }
+    Ok(key)
 }
 
 pub struct ThreadArrayChunk {
@@ -100,8 +193,8 @@ impl Iterator for CSVReader {
         self.line_number += row_count;
         match deserialize_batch(
             &self.buffer[..row_count],
-            &self.schema.fields,
-            None,
+            &self.fields,
+            self.projection.as_deref(),
             self.line_number - row_count,
             deserialize_column,
         ) {